@@ -37,9 +37,14 @@ fn main() {
             .read(&mut rgb_buffer)
             .expect("Failed to capture frame");
     }
+
+    let stats = stream.stats();
     println!(
-        "{} FPS ({} MB/s)",
-        1000 / (start.elapsed().as_millis() / count),
-        ((rgb_buffer.len() * 10) as u128 / start.elapsed().as_millis()) as f64 / 1000.0
+        "{:.1} FPS, jitter {:?}, {} dropped, {} duplicates ({} MB/s)",
+        stats.fps,
+        stats.jitter,
+        stats.dropped,
+        stats.duplicates,
+        ((rgb_buffer.len() * count) as u128 / start.elapsed().as_millis().max(1)) as f64 / 1000.0
     );
 }
@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+use std::io;
+
+use crate::frame::Frame;
+
+/// Maximum number of frames that can be averaged without overflowing the 16-bit accumulator
+pub const MAX_FRAMES: usize = u16::MAX as usize / u8::MAX as usize;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// Stacking mode
+pub enum Mode {
+    /// Per-pixel arithmetic mean, good against sensor noise
+    Mean,
+    /// Per-pixel median, also rejects outliers such as flicker or hot pixels
+    Median,
+}
+
+/// Checks that there is at least one frame and all frames have the same size
+pub(crate) fn check<'a>(frames: impl IntoIterator<Item = &'a Frame>) -> io::Result<()> {
+    let mut frames = frames.into_iter();
+    let first = match frames.next() {
+        Some(first) => first,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no frames to stack",
+            ))
+        }
+    };
+
+    if frames.any(|frame| {
+        frame.width != first.width
+            || frame.height != first.height
+            || frame.data.len() != first.data.len()
+    }) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "frame dimensions differ",
+        ));
+    }
+
+    Ok(())
+}
+
+/// Combines several frames of the same size into one
+///
+/// The resulting frame carries the sequence number and timestamp of the last input frame.
+/// Averaging is limited to [`MAX_FRAMES`] frames.
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::average::{stack, Mode};
+/// use openpnp_capture::Frame;
+/// use std::time::Instant;
+///
+/// let frame = |value| Frame {
+///     width: 1,
+///     height: 1,
+///     sequence: 0,
+///     timestamp: Instant::now(),
+///     data: vec![value; 3],
+/// };
+/// let frames = [frame(10), frame(20), frame(240)];
+/// assert_eq!(stack(&frames, Mode::Mean).unwrap().data, vec![90; 3]);
+/// assert_eq!(stack(&frames, Mode::Median).unwrap().data, vec![20; 3]);
+/// ```
+pub fn stack(frames: &[Frame], mode: Mode) -> io::Result<Frame> {
+    check(frames)?;
+
+    let last = &frames[frames.len() - 1];
+    let len = last.data.len();
+    let data = match mode {
+        Mode::Mean => {
+            if frames.len() > MAX_FRAMES {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "too many frames to average",
+                ));
+            }
+
+            let mut sum = vec![0u16; len];
+            for frame in frames {
+                for (acc, val) in sum.iter_mut().zip(&frame.data) {
+                    *acc += *val as u16;
+                }
+            }
+            divide(&sum, frames.len() as u16)
+        }
+        Mode::Median => {
+            let mut data = vec![0u8; len];
+            let mut samples = vec![0u8; frames.len()];
+            for (i, out) in data.iter_mut().enumerate() {
+                for (sample, frame) in samples.iter_mut().zip(frames) {
+                    *sample = frame.data[i];
+                }
+                let mid = samples.len() / 2;
+                *out = *samples.select_nth_unstable(mid).1;
+            }
+            data
+        }
+    };
+
+    Ok(Frame {
+        width: last.width,
+        height: last.height,
+        sequence: last.sequence,
+        timestamp: last.timestamp,
+        data,
+    })
+}
+
+/// Divides the accumulated sums with rounding
+fn divide(sum: &[u16], count: u16) -> Vec<u8> {
+    // Do the rounding in 32 bits, the sum itself may already be close to the limit
+    sum.iter()
+        .map(|acc| ((*acc as u32 + count as u32 / 2) / count as u32) as u8)
+        .collect()
+}
+
+#[derive(Debug)]
+/// Sliding window average over the most recent frames of a stream
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::average::RunningAverage;
+/// use openpnp_capture::Frame;
+/// use std::time::Instant;
+///
+/// let frame = |value| Frame {
+///     width: 1,
+///     height: 1,
+///     sequence: 0,
+///     timestamp: Instant::now(),
+///     data: vec![value; 3],
+/// };
+///
+/// let mut avg = RunningAverage::new(2);
+/// avg.push(frame(10));
+/// avg.push(frame(20));
+/// avg.push(frame(40));
+/// assert_eq!(avg.average().unwrap().data, vec![30; 3]);
+/// ```
+pub struct RunningAverage {
+    /// Window length
+    len: usize,
+    /// Frames inside the window
+    frames: VecDeque<Frame>,
+    /// Per-channel sum of all frames inside the window
+    sum: Vec<u16>,
+}
+
+impl RunningAverage {
+    /// Returns a filter averaging over the last `len` frames
+    ///
+    /// The length is clamped to `1..=MAX_FRAMES`.
+    pub fn new(len: usize) -> Self {
+        RunningAverage {
+            len: len.clamp(1, MAX_FRAMES),
+            frames: VecDeque::new(),
+            sum: Vec::new(),
+        }
+    }
+
+    /// Returns the window length
+    pub fn window(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true when no frames have been pushed since the last reset
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Discards all frames
+    pub fn reset(&mut self) {
+        self.frames.clear();
+        self.sum.clear();
+    }
+
+    /// Adds a frame, evicting the oldest one if the window is full
+    ///
+    /// A frame with different dimensions than the ones before restarts the filter.
+    pub fn push(&mut self, frame: Frame) {
+        if let Some(last) = self.frames.back() {
+            if last.width != frame.width
+                || last.height != frame.height
+                || last.data.len() != frame.data.len()
+            {
+                self.reset();
+            }
+        }
+
+        if self.sum.is_empty() {
+            self.sum.resize(frame.data.len(), 0);
+        }
+
+        if self.frames.len() == self.len {
+            if let Some(old) = self.frames.pop_front() {
+                for (acc, val) in self.sum.iter_mut().zip(&old.data) {
+                    *acc -= *val as u16;
+                }
+            }
+        }
+
+        for (acc, val) in self.sum.iter_mut().zip(&frame.data) {
+            *acc += *val as u16;
+        }
+        self.frames.push_back(frame);
+    }
+
+    /// Returns the average of the frames inside the window
+    pub fn average(&self) -> Option<Frame> {
+        let last = self.frames.back()?;
+        Some(Frame {
+            width: last.width,
+            height: last.height,
+            sequence: last.sequence,
+            timestamp: last.timestamp,
+            data: divide(&self.sum, self.frames.len() as u16),
+        })
+    }
+}
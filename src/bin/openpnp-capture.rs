@@ -0,0 +1,456 @@
+use clap::{Args, Parser, Subcommand};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::PathBuf;
+use std::time::Instant;
+
+use openpnp_capture::format::{FourCC, FrameRate};
+use openpnp_capture::property::Status;
+use openpnp_capture::{Device, Format, Property, Stream};
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+#[derive(Parser)]
+#[clap(
+    name = "openpnp-capture",
+    version,
+    about = "Inspect and control capture devices"
+)]
+struct Cli {
+    /// Print machine readable JSON instead of text
+    #[clap(long, global = true)]
+    json: bool,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List devices and their formats
+    List,
+    /// Show the properties of a device
+    Info {
+        /// Device index, unique ID or name
+        device: String,
+        #[clap(flatten)]
+        format: FormatArgs,
+    },
+    /// Capture a single frame into a PNG file
+    Snap {
+        /// Device index, unique ID or name
+        device: String,
+        /// Output file
+        #[clap(short, long)]
+        output: PathBuf,
+        /// Number of frames to discard before capturing
+        #[clap(long, default_value = "5")]
+        skip: u32,
+        #[clap(flatten)]
+        format: FormatArgs,
+    },
+    /// Set properties, e.g. `exposure=-6 focus=auto`
+    Set {
+        /// Device index, unique ID or name
+        device: String,
+        /// Assignments of the form `<property>=<value|auto|manual>`
+        #[clap(required = true)]
+        values: Vec<String>,
+    },
+    /// Measure the frame rate and throughput of a device
+    Bench {
+        /// Device index, unique ID or name
+        device: String,
+        /// Number of frames to capture
+        #[clap(short = 'n', long, default_value = "100")]
+        frames: u32,
+        #[clap(flatten)]
+        format: FormatArgs,
+    },
+}
+
+#[derive(Args)]
+struct FormatArgs {
+    /// Requested width in pixels
+    #[clap(long, default_value = "1280")]
+    width: u32,
+    /// Requested height in pixels
+    #[clap(long, default_value = "720")]
+    height: u32,
+    /// Requested frames per second, e.g. 30, 7.5 or 15/2
+    #[clap(long)]
+    fps: Option<FrameRate>,
+    /// Requested pixelformat, e.g. MJPG
+    #[clap(long)]
+    fourcc: Option<FourCC>,
+}
+
+impl FormatArgs {
+    fn format(&self) -> Result<Format> {
+        let mut format = Format::default().width(self.width).height(self.height);
+        if let Some(fps) = self.fps {
+            format = format.fps(fps);
+        }
+        if let Some(fourcc) = self.fourcc {
+            format = format.fourcc(fourcc);
+        }
+        Ok(format)
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let res = match &cli.command {
+        Command::List => list(&cli),
+        Command::Info { device, format } => info(&cli, device, format),
+        Command::Snap {
+            device,
+            output,
+            skip,
+            format,
+        } => snap(&cli, device, output, *skip, format),
+        Command::Set { device, values } => set(&cli, device, values),
+        Command::Bench {
+            device,
+            frames,
+            format,
+        } => bench(&cli, device, *frames, format),
+    };
+
+    if let Err(e) = res {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Looks up a device by index, unique ID or name (in that order)
+fn find_device(spec: &str) -> Result<Device> {
+    let mut devices: Vec<Device> = Device::enumerate()
+        .into_iter()
+        .filter_map(Device::new)
+        .collect();
+
+    if let Ok(index) = spec.parse::<u32>() {
+        if let Some(pos) = devices.iter().position(|dev| dev.index == index) {
+            return Ok(devices.swap_remove(pos));
+        }
+    }
+
+    let mut matches: Vec<Device> = devices
+        .into_iter()
+        .filter(|dev| dev.id == spec || dev.name == spec)
+        .collect();
+    match matches.len() {
+        0 => Err(format!("no such device: {}", spec).into()),
+        1 => Ok(matches.remove(0)),
+        _ => Err(format!("ambiguous device: {}, use the index or unique ID", spec).into()),
+    }
+}
+
+fn open_stream(dev: &Device, format: &Format) -> Result<Stream> {
+    Stream::new(dev, format)
+        .ok_or_else(|| format!("failed to open stream on device {}", dev.index).into())
+}
+
+fn print_json(value: &Value) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn format_json(format: &Format) -> Value {
+    json!({
+        "width": format.width,
+        "height": format.height,
+        "fourcc": format.fourcc.to_string(),
+        "fps": format.fps.as_f64(),
+    })
+}
+
+fn list(cli: &Cli) -> Result<()> {
+    let mut devices = Vec::new();
+    for index in Device::enumerate() {
+        let dev = match Device::new(index) {
+            Some(dev) => dev,
+            None => continue,
+        };
+
+        // Group the formats by pixelformat, keeping the order reported by the driver
+        let mut groups: Vec<(String, Vec<Format>)> = Vec::new();
+        for format in dev.formats() {
+            let fourcc = format.fourcc.to_string();
+            match groups.iter_mut().find(|(key, _)| *key == fourcc) {
+                Some((_, formats)) => formats.push(format),
+                None => groups.push((fourcc, vec![format])),
+            }
+        }
+
+        devices.push((dev, groups));
+    }
+
+    if cli.json {
+        let devices: Vec<Value> = devices
+            .iter()
+            .map(|(dev, groups)| {
+                let formats: serde_json::Map<String, Value> = groups
+                    .iter()
+                    .map(|(fourcc, formats)| {
+                        let sizes = formats
+                            .iter()
+                            .map(|f| {
+                                json!({
+                                    "width": f.width,
+                                    "height": f.height,
+                                    "fps": f.fps.as_f64(),
+                                })
+                            })
+                            .collect();
+                        (fourcc.clone(), Value::Array(sizes))
+                    })
+                    .collect();
+                let info = dev.info().unwrap_or_default();
+                json!({
+                    "index": dev.index,
+                    "name": dev.name,
+                    "id": dev.id,
+                    "path": info.path,
+                    "bus": info.bus,
+                    "driver": info.driver,
+                    "vendor_id": info.vendor_id,
+                    "product_id": info.product_id,
+                    "serial": info.serial,
+                    "formats": formats,
+                })
+            })
+            .collect();
+        return print_json(&Value::Array(devices));
+    }
+
+    println!("Found {} devices.", devices.len());
+    for (dev, groups) in &devices {
+        println!("[{}] {}", dev.index, dev.name);
+        println!("  ID = {}", dev.id);
+        if let Some(info) = dev.info() {
+            println!("  Path = {}", info.path);
+            if !info.bus.is_empty() {
+                println!("  Bus = {}", info.bus);
+            }
+            if let (Some(vid), Some(pid)) = (info.vendor_id, info.product_id) {
+                println!("  USB = {:04x}:{:04x}", vid, pid);
+            }
+            if let Some(serial) = &info.serial {
+                println!("  Serial = {}", serial);
+            }
+        }
+        for (fourcc, formats) in groups {
+            println!("  {}:", fourcc);
+            for format in formats {
+                println!("    {}x{}@{}", format.width, format.height, format.fps);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn info(cli: &Cli, device: &str, format: &FormatArgs) -> Result<()> {
+    let dev = find_device(device)?;
+    let stream = open_stream(&dev, &format.format()?)?;
+
+    // Unsupported properties are skipped, unsupported auto modes are reported as absent
+    let mut props = BTreeMap::new();
+    for prop in Property::all() {
+        let limits = match stream.property_limits(*prop) {
+            Ok(limits) => limits,
+            Err(_) => continue,
+        };
+        let value = stream.property(*prop).ok();
+        let auto = stream.auto_property(*prop).ok();
+        props.insert(*prop, (limits, value, auto));
+    }
+
+    if cli.json {
+        let props: serde_json::Map<String, Value> = props
+            .iter()
+            .map(|(prop, (limits, value, auto))| {
+                let value = json!({
+                    "min": limits.min,
+                    "max": limits.max,
+                    "default": limits.default,
+                    "value": value,
+                    "auto": auto,
+                });
+                (prop.to_string(), value)
+            })
+            .collect();
+        return print_json(&json!({
+            "index": dev.index,
+            "name": dev.name,
+            "id": dev.id,
+            "format": format_json(&stream.format()),
+            "properties": props,
+        }));
+    }
+
+    let active = stream.format();
+    println!("[{}] {}", dev.index, dev.name);
+    println!("  ID = {}", dev.id);
+    println!(
+        "  Format = {} {}x{}@{}",
+        active.fourcc, active.width, active.height, active.fps
+    );
+    println!("  Properties:");
+    for (prop, (limits, value, auto)) in &props {
+        let value = value.map_or("?".to_string(), |v| v.to_string());
+        let auto = match auto {
+            Some(true) => " (auto)",
+            Some(false) => " (manual)",
+            None => "",
+        };
+        println!(
+            "    {:<14} {:>6}{} [min = {}, max = {}, default = {}]",
+            prop, value, auto, limits.min, limits.max, limits.default
+        );
+    }
+
+    Ok(())
+}
+
+fn snap(cli: &Cli, device: &str, output: &PathBuf, skip: u32, format: &FormatArgs) -> Result<()> {
+    let dev = find_device(device)?;
+    let mut stream = open_stream(&dev, &format.format()?)?;
+
+    // Give auto exposure and white balance some time to settle
+    for _ in 0..skip {
+        stream.advance();
+    }
+    stream.advance();
+
+    let mut buf = Vec::new();
+    stream.read(&mut buf)?;
+
+    let active = stream.format();
+    let file = File::create(output)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), active.width, active.height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()?
+        .write_image_data(&buf)
+        .map_err(|e| io::Error::other(e.to_string()))?;
+
+    if cli.json {
+        return print_json(&json!({
+            "output": output,
+            "format": format_json(&active),
+        }));
+    }
+
+    println!(
+        "Wrote {}x{} frame to {}",
+        active.width,
+        active.height,
+        output.display()
+    );
+    Ok(())
+}
+
+fn set(cli: &Cli, device: &str, values: &[String]) -> Result<()> {
+    let dev = find_device(device)?;
+
+    // Parse everything up front so we do not apply half of a bad command line
+    let mut assignments = Vec::new();
+    for value in values {
+        let (prop, value) = value
+            .split_once('=')
+            .ok_or_else(|| format!("expected <property>=<value>, got: {}", value))?;
+        assignments.push((prop.parse::<Property>()?, value.to_lowercase()));
+    }
+
+    // Properties do not depend on the format, so any will do
+    let stream = open_stream(&dev, &Format::default())?;
+
+    let mut applied = serde_json::Map::new();
+    for (prop, value) in assignments {
+        let mut status = None;
+        match value.as_str() {
+            "auto" => stream.set_auto_property(prop, true)?,
+            "manual" => stream.set_auto_property(prop, false)?,
+            value => {
+                let value = value
+                    .parse::<i32>()
+                    .map_err(|_| format!("invalid value for {}: {}", prop, value))?;
+                // Manual values are ignored while the automatic mode is active
+                let _ = stream.set_auto_property(prop, false);
+                let applied = stream.set_property_checked(prop, value)?;
+                if applied.status != Status::Exact && !cli.json {
+                    eprintln!(
+                        "{}: driver applied {} instead of {} ({:?})",
+                        prop, applied.value, applied.requested, applied.status
+                    );
+                }
+                status = Some(format!("{:?}", applied.status).to_lowercase());
+            }
+        }
+
+        let current = json!({
+            "value": stream.property(prop).ok(),
+            "auto": stream.auto_property(prop).ok(),
+            "status": status,
+        });
+        if !cli.json {
+            println!("{} = {}", prop, value);
+        }
+        applied.insert(prop.to_string(), current);
+    }
+
+    if cli.json {
+        return print_json(&Value::Object(applied));
+    }
+    Ok(())
+}
+
+fn bench(cli: &Cli, device: &str, frames: u32, format: &FormatArgs) -> Result<()> {
+    let dev = find_device(device)?;
+    let mut stream = open_stream(&dev, &format.format()?)?;
+    let mut buf = Vec::new();
+
+    // Warmup
+    stream.advance();
+    stream.read(&mut buf)?;
+
+    let first_count = stream.frame_count();
+    let start = Instant::now();
+    for _ in 0..frames {
+        stream.advance();
+        stream.read(&mut buf)?;
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    let captured = stream.frame_count().wrapping_sub(first_count);
+
+    let fps = frames as f64 / elapsed;
+    let throughput = (buf.len() as f64 * frames as f64) / elapsed / 1_000_000.0;
+    let active = stream.format();
+
+    if cli.json {
+        return print_json(&json!({
+            "format": format_json(&active),
+            "frames": frames,
+            "captured": captured,
+            "seconds": elapsed,
+            "fps": fps,
+            "mbps": throughput,
+        }));
+    }
+
+    println!(
+        "{} {}x{}@{}: {} frames in {:.2} s",
+        active.fourcc, active.width, active.height, active.fps, frames, elapsed
+    );
+    println!("{:.2} FPS ({:.2} MB/s)", fps, throughput);
+    println!("{} frames captured by the device", captured);
+    Ok(())
+}
@@ -0,0 +1,42 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::frame::Frame;
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Exposure and optional gain of a single bracketed frame
+pub struct ExposureSetting {
+    /// Exposure value in device units
+    pub exposure: i32,
+    /// Gain value in device units, the current gain is kept if unset
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub gain: Option<i32>,
+}
+
+impl ExposureSetting {
+    /// Returns a setting for the given exposure, keeping the current gain
+    pub fn new(exposure: i32) -> Self {
+        ExposureSetting {
+            exposure,
+            gain: None,
+        }
+    }
+
+    /// Builder: sets the gain
+    pub fn gain(mut self, gain: i32) -> Self {
+        self.gain = Some(gain);
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Frame of a bracket
+pub struct BracketFrame {
+    /// Requested setting
+    pub requested: ExposureSetting,
+    /// Setting read back from the driver after applying the requested one
+    pub applied: ExposureSetting,
+    /// Captured frame
+    pub frame: Frame,
+}
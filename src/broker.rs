@@ -0,0 +1,764 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{fence, AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::format::Format;
+use crate::frame::Frame;
+use crate::property::{Limits, Property};
+use crate::stream::Stream;
+
+/// Number of frames held in the shared ring buffer
+pub const SLOTS: u32 = 4;
+
+/// Magic bytes of the shared ring buffer
+const MAGIC: &[u8; 4] = b"OPSM";
+
+/// Layout version of the shared ring buffer
+const VERSION: u32 = 1;
+
+/// Size of the ring buffer header
+const HEADER: usize = 64;
+
+/// Size of the header in front of every frame
+const SLOT_HEADER: usize = 32;
+
+/// Interval at which idle threads check whether the broker is shutting down
+const TICK: Duration = Duration::from_millis(100);
+
+/// Attempts to read a frame before giving up when the broker keeps overwriting it
+const READ_ATTEMPTS: usize = 8;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// Control request of a client
+enum Request {
+    Hello,
+    Property(Property),
+    SetProperty(Property, i32),
+    AutoProperty(Property),
+    SetAutoProperty(Property, bool),
+    PropertyLimits(Property),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+/// Answer of the broker to a control request
+enum Response {
+    Hello { frames: PathBuf, format: Format },
+    Value(i32),
+    Enabled(bool),
+    Limits(Limits),
+    Done,
+    Error(String),
+}
+
+/// Memory mapped file
+#[derive(Debug)]
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// The mapping is only accessed through the seqlock protocol of `Ring`
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    fn new(file: &fs::File, len: usize, writable: bool) -> io::Result<Self> {
+        let prot = match writable {
+            true => libc::PROT_READ | libc::PROT_WRITE,
+            false => libc::PROT_READ,
+        };
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                prot,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mapping {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+    }
+}
+
+/// Ring buffer of frames in shared memory
+///
+/// Every slot is guarded by a sequence number that is odd while the broker writes the slot, so
+/// readers can detect frames that changed while they were copied and retry.
+#[derive(Debug)]
+struct Ring {
+    map: Mapping,
+    slots: u32,
+    capacity: usize,
+}
+
+impl Ring {
+    /// Returns the distance between two slots
+    fn stride(capacity: usize) -> usize {
+        SLOT_HEADER + capacity.div_ceil(8) * 8
+    }
+
+    /// Creates the ring buffer file for frames of up to `capacity` bytes
+    ///
+    /// The file is set up under a temporary name and renamed into place, so readers that still
+    /// have a previous file at `path` mapped keep their own copy.
+    fn create(path: &Path, slots: u32, capacity: usize) -> io::Result<Self> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(format!(".{}.tmp", std::process::id()));
+        let tmp = PathBuf::from(tmp);
+
+        let ring = Ring::create_new(&tmp, slots, capacity).and_then(|ring| {
+            fs::rename(&tmp, path)?;
+            Ok(ring)
+        });
+        if ring.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        ring
+    }
+
+    fn create_new(path: &Path, slots: u32, capacity: usize) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(path)?;
+        let len = HEADER + slots as usize * Ring::stride(capacity);
+        file.set_len(len as u64)?;
+
+        let ring = Ring {
+            map: Mapping::new(&file, len, true)?,
+            slots,
+            capacity,
+        };
+        unsafe {
+            ptr::copy_nonoverlapping(MAGIC.as_ptr(), ring.map.ptr, 4);
+            ring.write_u32(4, VERSION);
+            ring.write_u32(8, slots);
+            ring.write_u32(12, capacity as u32);
+        }
+        Ok(ring)
+    }
+
+    /// Opens the ring buffer file of a broker for reading
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).open(path)?;
+        let len = file.metadata()?.len() as usize;
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid frame buffer");
+        if len < HEADER {
+            return Err(invalid());
+        }
+
+        let map = Mapping::new(&file, len, false)?;
+        let mut ring = Ring {
+            map,
+            slots: 0,
+            capacity: 0,
+        };
+        unsafe {
+            if std::slice::from_raw_parts(ring.map.ptr, 4) != MAGIC || ring.read_u32(4) != VERSION {
+                return Err(invalid());
+            }
+            ring.slots = ring.read_u32(8);
+            ring.capacity = ring.read_u32(12) as usize;
+        }
+        if ring.slots == 0 || len < HEADER + ring.slots as usize * Ring::stride(ring.capacity) {
+            return Err(invalid());
+        }
+        Ok(ring)
+    }
+
+    unsafe fn read_u32(&self, offset: usize) -> u32 {
+        ptr::read_volatile(self.map.ptr.add(offset) as *const u32)
+    }
+
+    unsafe fn write_u32(&self, offset: usize, value: u32) {
+        ptr::write_volatile(self.map.ptr.add(offset) as *mut u32, value)
+    }
+
+    unsafe fn atomic(&self, offset: usize) -> &AtomicU64 {
+        &*(self.map.ptr.add(offset) as *const AtomicU64)
+    }
+
+    /// Number of frames published so far
+    fn latest(&self) -> u64 {
+        unsafe { self.atomic(16) }.load(Ordering::Acquire)
+    }
+
+    /// Returns the offset of the slot holding a frame
+    fn slot(&self, n: u64) -> usize {
+        HEADER + (n % self.slots as u64) as usize * Ring::stride(self.capacity)
+    }
+
+    /// Publishes a frame
+    fn publish(&self, frame: &Frame) {
+        let n = self.latest() + 1;
+        let slot = self.slot(n);
+        let len = frame.data.len().min(self.capacity);
+        let timestamp = SystemTime::now()
+            .checked_sub(frame.timestamp.elapsed())
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |time| time.as_nanos() as u64);
+
+        unsafe {
+            let seq = self.atomic(slot);
+            seq.store(2 * n - 1, Ordering::Relaxed);
+            fence(Ordering::Release);
+            self.write_u32(slot + 8, frame.sequence);
+            self.write_u32(slot + 12, frame.width);
+            self.write_u32(slot + 16, frame.height);
+            self.write_u32(slot + 20, len as u32);
+            ptr::write_volatile(self.map.ptr.add(slot + 24) as *mut u64, timestamp);
+            ptr::copy_nonoverlapping(
+                frame.data.as_ptr(),
+                self.map.ptr.add(slot + SLOT_HEADER),
+                len,
+            );
+            seq.store(2 * n, Ordering::Release);
+            self.atomic(16).store(n, Ordering::Release);
+        }
+    }
+
+    /// Copies the most recent frame and returns its number, or `None` if there is none yet
+    ///
+    /// A frame that is overwritten while it is copied is read again. Fails with
+    /// [`io::ErrorKind::WouldBlock`] if that happens [`READ_ATTEMPTS`] times in a row, i.e.
+    /// the reader is too slow to copy a frame within [`SLOTS`] frame intervals.
+    fn read(&self, frame: &mut Frame) -> io::Result<Option<u64>> {
+        for _ in 0..READ_ATTEMPTS {
+            let n = self.latest();
+            if n == 0 {
+                return Ok(None);
+            }
+
+            let slot = self.slot(n);
+            unsafe {
+                let seq = self.atomic(slot);
+                if seq.load(Ordering::Acquire) != 2 * n {
+                    continue;
+                }
+                frame.sequence = self.read_u32(slot + 8);
+                frame.width = self.read_u32(slot + 12);
+                frame.height = self.read_u32(slot + 16);
+                let len = (self.read_u32(slot + 20) as usize).min(self.capacity);
+                let timestamp = ptr::read_volatile(self.map.ptr.add(slot + 24) as *const u64);
+                frame.data.resize(len, 0);
+                ptr::copy_nonoverlapping(
+                    self.map.ptr.add(slot + SLOT_HEADER),
+                    frame.data.as_mut_ptr(),
+                    len,
+                );
+                fence(Ordering::Acquire);
+                if seq.load(Ordering::Relaxed) != 2 * n {
+                    continue;
+                }
+
+                // Convert the wall clock time of the broker to a host instant of this process
+                let age = SystemTime::now()
+                    .duration_since(UNIX_EPOCH + Duration::from_nanos(timestamp))
+                    .unwrap_or_default();
+                let now = Instant::now();
+                frame.timestamp = now.checked_sub(age).unwrap_or(now);
+            }
+            return Ok(Some(n));
+        }
+        Err(io::Error::new(
+            io::ErrorKind::WouldBlock,
+            "frame was overwritten while reading",
+        ))
+    }
+}
+
+#[derive(Debug)]
+/// Shares a stream with other local processes
+///
+/// The broker owns the stream and reads every new frame into a ring buffer in shared memory.
+/// Other processes connect to its Unix socket with [`SharedStream::connect`], read frames
+/// straight from the shared memory and have property requests forwarded to the stream.
+///
+/// The ring buffer lives in a file next to the socket, with the `frames` extension. Put the
+/// socket on a memory backed file system such as `/dev/shm` or `/run` so the frames never
+/// touch a disk. Both are only accessible to the user running the broker.
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::broker::{Broker, SharedStream};
+/// use openpnp_capture::{Device, Format, Stream};
+///
+/// let dev = Device::new(0);
+/// if let Some(dev) = &dev {
+///     if let Some(stream) = Stream::new(&dev, &Format::default()) {
+///         let socket = std::env::temp_dir().join("openpnp-capture-top.sock");
+///         let broker = Broker::new(stream, &socket).unwrap();
+///
+///         // Usually in another process
+///         let shared = SharedStream::connect(&socket).unwrap();
+///         if shared.poll() {
+///             let frame = shared.read_frame().unwrap();
+///             println!("Frame {} of {:?}", frame.sequence, shared.format());
+///         }
+///         drop(broker);
+///     }
+/// }
+/// ```
+pub struct Broker {
+    socket: PathBuf,
+    frames: PathBuf,
+    stream: Arc<Stream>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Broker {
+    /// Starts sharing a stream on a Unix socket
+    ///
+    /// A stale socket left behind at `socket` is replaced. Fails with
+    /// [`io::ErrorKind::AddrInUse`] if another broker is still listening on it. Orientation and
+    /// processing of the stream must be set up before, since the frame size cannot change
+    /// afterwards.
+    pub fn new<P: AsRef<Path>>(stream: Stream, socket: P) -> io::Result<Self> {
+        let socket = socket.as_ref().to_path_buf();
+        if let Ok(meta) = fs::symlink_metadata(&socket) {
+            if meta.file_type().is_socket() {
+                if UnixStream::connect(&socket).is_ok() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("a broker is already listening on {}", socket.display()),
+                    ));
+                }
+                fs::remove_file(&socket)?;
+            }
+        }
+        let listener = UnixListener::bind(&socket)?;
+        fs::set_permissions(&socket, fs::Permissions::from_mode(0o600))?;
+
+        let format = stream.format();
+        let frames = socket.with_extension("frames");
+        let capacity = format.width as usize * format.height as usize * 3;
+        let ring = Arc::new(Ring::create(&frames, SLOTS, capacity)?);
+
+        let stream = Arc::new(stream);
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let capture = {
+            let (stream, stop, ring) = (stream.clone(), stop.clone(), ring);
+            thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    if stream.poll() {
+                        if let Ok(frame) = stream.read_frame() {
+                            ring.publish(&frame);
+                        }
+                    } else {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                }
+            })
+        };
+
+        let control = {
+            let (stream, stop) = (stream.clone(), stop.clone());
+            let hello = Response::Hello {
+                frames: frames.clone(),
+                format,
+            };
+            thread::spawn(move || {
+                let mut clients = Vec::new();
+                for conn in listener.incoming() {
+                    if stop.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    // Forget the clients that disconnected
+                    clients.retain(|client: &JoinHandle<()>| !client.is_finished());
+                    if let Ok(conn) = conn {
+                        let (stream, stop, hello) = (stream.clone(), stop.clone(), hello.clone());
+                        clients.push(thread::spawn(move || {
+                            // A client that goes away must not stop the broker
+                            let _ = serve(conn, &stream, &stop, &hello);
+                        }));
+                    }
+                }
+                for client in clients {
+                    let _ = client.join();
+                }
+            })
+        };
+
+        Ok(Broker {
+            socket,
+            frames,
+            stream,
+            stop,
+            threads: vec![capture, control],
+        })
+    }
+
+    /// Returns the shared stream, e.g. for its statistics
+    pub fn stream(&self) -> &Stream {
+        &self.stream
+    }
+
+    /// Returns the path of the control socket
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+}
+
+impl Drop for Broker {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        // Wake up the control thread waiting for connections
+        let _ = UnixStream::connect(&self.socket);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+        let _ = fs::remove_file(&self.socket);
+        let _ = fs::remove_file(&self.frames);
+    }
+}
+
+/// Answers the control requests of a client until it disconnects or the broker stops
+fn serve(conn: UnixStream, stream: &Stream, stop: &AtomicBool, hello: &Response) -> io::Result<()> {
+    conn.set_read_timeout(Some(TICK))?;
+    let mut writer = conn.try_clone()?;
+    let mut reader = BufReader::new(conn);
+    let mut line = String::new();
+
+    while !stop.load(Ordering::Relaxed) {
+        match reader.read_line(&mut line) {
+            Ok(0) => return Ok(()),
+            Ok(_) => {}
+            // Partial lines stay in the buffer until the rest arrives
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue
+            }
+            Err(e) => return Err(e),
+        }
+
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => handle(stream, hello, request),
+            Err(e) => Response::Error(format!("invalid request: {}", e)),
+        };
+        line.clear();
+
+        let response = serde_json::to_string(&response).map_err(io::Error::other)?;
+        writeln!(writer, "{}", response)?;
+    }
+    Ok(())
+}
+
+/// Forwards a request to the stream, `hello` is the answer to the handshake
+fn handle(stream: &Stream, hello: &Response, request: Request) -> Response {
+    let result = match request {
+        Request::Hello => Ok(hello.clone()),
+        Request::Property(prop) => stream.property(prop).map(Response::Value),
+        Request::SetProperty(prop, value) => {
+            stream.set_property(prop, value).map(|_| Response::Done)
+        }
+        Request::AutoProperty(prop) => stream.auto_property(prop).map(Response::Enabled),
+        Request::SetAutoProperty(prop, enabled) => stream
+            .set_auto_property(prop, enabled)
+            .map(|_| Response::Done),
+        Request::PropertyLimits(prop) => stream.property_limits(prop).map(Response::Limits),
+    };
+    result.unwrap_or_else(|e| Response::Error(e.to_string()))
+}
+
+#[derive(Debug)]
+/// Stream shared by a [`Broker`] in another process
+///
+/// Frames are read from shared memory, property requests are forwarded to the broker.
+pub struct SharedStream {
+    control: Mutex<BufReader<UnixStream>>,
+    ring: Ring,
+    format: Format,
+    /// Number of the last frame read
+    last: AtomicU64,
+}
+
+impl SharedStream {
+    /// Connects to the broker listening on a Unix socket
+    pub fn connect<P: AsRef<Path>>(socket: P) -> io::Result<Self> {
+        let conn = UnixStream::connect(socket)?;
+        let mut control = BufReader::new(conn);
+        match request(&mut control, &Request::Hello)? {
+            Response::Hello { frames, format } => Ok(SharedStream {
+                control: Mutex::new(control),
+                ring: Ring::open(&frames)?,
+                format,
+                last: AtomicU64::new(0),
+            }),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "unexpected response from broker",
+            )),
+        }
+    }
+
+    /// Returns the format of the frames
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Returns true if a new frame was published since the last read
+    pub fn poll(&self) -> bool {
+        self.ring.latest() != self.last.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of frames the broker published
+    pub fn frame_count(&self) -> u32 {
+        self.ring.latest() as u32
+    }
+
+    /// Copy the current frame into a buffer
+    pub fn read(&self, buf: &mut Vec<u8>) -> io::Result<()> {
+        let mut frame = self.empty_frame(std::mem::take(buf));
+        let result = self.read_into(&mut frame);
+        *buf = frame.data;
+        result
+    }
+
+    /// Copy the current frame into a new [`Frame`]
+    ///
+    /// Fails with [`io::ErrorKind::WouldBlock`] if no frame was published yet, or if the broker
+    /// kept overwriting the frame while it was copied.
+    pub fn read_frame(&self) -> io::Result<Frame> {
+        let mut frame = self.empty_frame(Vec::new());
+        self.read_into(&mut frame)?;
+        Ok(frame)
+    }
+
+    fn empty_frame(&self, data: Vec<u8>) -> Frame {
+        Frame {
+            width: self.format.width,
+            height: self.format.height,
+            sequence: 0,
+            timestamp: Instant::now(),
+            data,
+        }
+    }
+
+    /// Copy the current frame into `frame`, reusing its buffer
+    fn read_into(&self, frame: &mut Frame) -> io::Result<()> {
+        match self.ring.read(frame)? {
+            Some(n) => {
+                self.last.store(n, Ordering::Relaxed);
+                Ok(())
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                "no frame published yet",
+            )),
+        }
+    }
+
+    fn request(&self, request: &Request) -> io::Result<Response> {
+        let mut control = self.control.lock().unwrap();
+        match self::request(&mut control, request)? {
+            Response::Error(e) => Err(io::Error::other(e)),
+            response => Ok(response),
+        }
+    }
+
+    /// Returns the value range of a property
+    pub fn property_limits(&self, prop: Property) -> io::Result<Limits> {
+        match self.request(&Request::PropertyLimits(prop))? {
+            Response::Limits(limits) => Ok(limits),
+            _ => Err(unexpected()),
+        }
+    }
+
+    /// Returns the current value of a property
+    pub fn property(&self, prop: Property) -> io::Result<i32> {
+        match self.request(&Request::Property(prop))? {
+            Response::Value(value) => Ok(value),
+            _ => Err(unexpected()),
+        }
+    }
+
+    /// Sets the value of a property
+    pub fn set_property(&self, prop: Property, value: i32) -> io::Result<()> {
+        match self.request(&Request::SetProperty(prop, value))? {
+            Response::Done => Ok(()),
+            _ => Err(unexpected()),
+        }
+    }
+
+    /// Returns true when the automatic mode of a property is enabled
+    pub fn auto_property(&self, prop: Property) -> io::Result<bool> {
+        match self.request(&Request::AutoProperty(prop))? {
+            Response::Enabled(enabled) => Ok(enabled),
+            _ => Err(unexpected()),
+        }
+    }
+
+    /// Enables or disables the automatic mode of a property
+    pub fn set_auto_property(&self, prop: Property, enabled: bool) -> io::Result<()> {
+        match self.request(&Request::SetAutoProperty(prop, enabled))? {
+            Response::Done => Ok(()),
+            _ => Err(unexpected()),
+        }
+    }
+}
+
+/// Sends a request to the broker and waits for the response
+fn request(control: &mut BufReader<UnixStream>, request: &Request) -> io::Result<Response> {
+    let request = serde_json::to_string(request).map_err(io::Error::other)?;
+    writeln!(control.get_mut(), "{}", request)?;
+
+    let mut line = String::new();
+    if control.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "broker closed the connection",
+        ));
+    }
+    serde_json::from_str(&line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn unexpected() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "unexpected response from broker",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(sequence: u32, len: usize) -> Frame {
+        Frame {
+            width: len as u32 / 3,
+            height: 1,
+            sequence,
+            timestamp: Instant::now(),
+            data: vec![sequence as u8; len],
+        }
+    }
+
+    fn empty() -> Frame {
+        frame(0, 0)
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("opc-broker-{}-{}.frames", name, std::process::id()))
+    }
+
+    #[test]
+    fn ring_round_trip() {
+        let path = temp_path("round-trip");
+        let writer = Ring::create(&path, SLOTS, 30).unwrap();
+        let reader = Ring::open(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+
+        let mut read = empty();
+        assert_eq!(reader.read(&mut read).unwrap(), None);
+
+        // Wraps around the slots
+        for sequence in 1..=2 * SLOTS + 1 {
+            writer.publish(&frame(sequence, 30));
+            assert_eq!(reader.read(&mut read).unwrap(), Some(sequence as u64));
+            assert_eq!(read.sequence, sequence);
+            assert_eq!((read.width, read.height), (10, 1));
+            assert_eq!(read.data, vec![sequence as u8; 30]);
+        }
+
+        // Oversized frames are cut to the capacity
+        writer.publish(&frame(100, 60));
+        reader.read(&mut read).unwrap();
+        assert_eq!(read.data.len(), 30);
+    }
+
+    #[test]
+    fn ring_retries_overwritten_frames() {
+        let path = temp_path("overwritten");
+        let writer = Ring::create(&path, SLOTS, 30).unwrap();
+        let reader = Ring::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        writer.publish(&frame(1, 30));
+        let n = writer.latest();
+
+        // The slot looks like it is being written, as if the broker overtook the reader
+        let seq = unsafe { writer.atomic(writer.slot(n)) };
+        seq.store(2 * n + 2 * SLOTS as u64 - 1, Ordering::Release);
+        let mut read = empty();
+        let error = reader.read(&mut read).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::WouldBlock);
+
+        // The next frame is read once it is complete
+        writer.publish(&frame(2, 30));
+        assert_eq!(reader.read(&mut read).unwrap(), Some(n + 1));
+        assert_eq!(read.data, vec![2; 30]);
+    }
+
+    #[test]
+    fn ring_reads_consistent_frames_while_publishing() {
+        let path = temp_path("concurrent");
+        let writer = Ring::create(&path, 2, 4096).unwrap();
+        let reader = Ring::open(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let publisher = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let mut sequence = 0u32;
+                while !stop.load(Ordering::Relaxed) {
+                    sequence = sequence.wrapping_add(1);
+                    writer.publish(&frame(sequence, 4096));
+                }
+            })
+        };
+
+        let mut read = empty();
+        let mut frames = 0;
+        while frames < 1000 {
+            match reader.read(&mut read) {
+                Ok(Some(_)) => {
+                    // A torn frame would mix the bytes of two sequence numbers
+                    let byte = read.sequence as u8;
+                    assert!(read.data.iter().all(|&b| b == byte));
+                    frames += 1;
+                }
+                Ok(None) => {}
+                Err(e) => assert_eq!(e.kind(), io::ErrorKind::WouldBlock),
+            }
+        }
+        stop.store(true, Ordering::Relaxed);
+        publisher.join().unwrap();
+    }
+}
@@ -0,0 +1,1260 @@
+use std::fmt;
+use std::io;
+
+#[cfg(feature = "profile")]
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::frame::Frame;
+#[cfg(feature = "profile")]
+use crate::profile;
+use crate::roi::Roi;
+
+/// Minimum number of views needed to calibrate
+pub const MIN_VIEWS: usize = 3;
+
+/// Image point in pixels, `(x, y)`
+pub type Point = (f64, f64);
+
+type Mat3 = [[f64; 3]; 3];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+/// Calibration target
+pub enum Pattern {
+    /// Black and white checkerboard
+    Checkerboard {
+        /// Inner corners per row
+        columns: u32,
+        /// Inner corners per column
+        rows: u32,
+        /// Side length of a square, e.g. in millimetres
+        square: f64,
+    },
+    /// Grid of dark dots on a light background
+    DotGrid {
+        /// Dots per row
+        columns: u32,
+        /// Dots per column
+        rows: u32,
+        /// Distance between dot centers, e.g. in millimetres
+        spacing: f64,
+    },
+}
+
+impl Pattern {
+    /// Returns the number of points per row and column and their distance
+    fn grid(&self) -> (usize, usize, f64) {
+        match *self {
+            Pattern::Checkerboard {
+                columns,
+                rows,
+                square,
+            } => (columns as usize, rows as usize, square),
+            Pattern::DotGrid {
+                columns,
+                rows,
+                spacing,
+            } => (columns as usize, rows as usize, spacing),
+        }
+    }
+
+    /// Returns the positions of the pattern points on the target, row by row
+    pub fn object_points(&self) -> Vec<Point> {
+        let (columns, rows, spacing) = self.grid();
+        (0..rows)
+            .flat_map(|y| (0..columns).map(move |x| (x as f64 * spacing, y as f64 * spacing)))
+            .collect()
+    }
+
+    /// Locates the pattern in a frame
+    ///
+    /// Returns the image points in the order of [`Pattern::object_points`], or `None` if the
+    /// pattern is not completely visible. The target has to be surrounded by a light margin
+    /// and must not touch the frame border.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::calibration::Pattern;
+    /// use openpnp_capture::Frame;
+    /// use std::time::Instant;
+    ///
+    /// // White frame with a 3x2 grid of black 4x4 dots, 10 pixels apart
+    /// let (width, height) = (40, 30);
+    /// let mut data = vec![255; width * height * 3];
+    /// for (cx, cy) in [(10, 10), (20, 10), (30, 10), (10, 20), (20, 20), (30, 20)] {
+    ///     for y in cy - 2..cy + 2 {
+    ///         for x in cx - 2..cx + 2 {
+    ///             let i = (y * width + x) * 3;
+    ///             data[i..i + 3].copy_from_slice(&[0, 0, 0]);
+    ///         }
+    ///     }
+    /// }
+    /// let frame = Frame {
+    ///     width: width as u32,
+    ///     height: height as u32,
+    ///     sequence: 0,
+    ///     timestamp: Instant::now(),
+    ///     data,
+    /// };
+    ///
+    /// let pattern = Pattern::DotGrid { columns: 3, rows: 2, spacing: 1.0 };
+    /// let points = pattern.detect(&frame).unwrap();
+    /// assert_eq!(points[0], (9.5, 9.5));
+    /// assert_eq!(points[5], (29.5, 19.5));
+    /// ```
+    pub fn detect(&self, frame: &Frame) -> Option<Vec<Point>> {
+        let (columns, rows, _) = self.grid();
+        if columns < 2 || rows < 2 {
+            return None;
+        }
+
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        let luma = frame.luma(&Roi::full(frame.width, frame.height));
+        let threshold = otsu(&luma);
+        let dark: Vec<bool> = luma.iter().map(|&v| v <= threshold).collect();
+
+        let points = match self {
+            Pattern::Checkerboard { .. } => {
+                // Shrink the dark squares so diagonal neighbours no longer touch
+                let squares = typical(blobs(&erode(&dark, width, height), width, height, false));
+
+                // Every inner corner is shared by exactly two diagonally adjacent dark squares
+                let mut corners = Vec::new();
+                let mut radius = 0.0;
+                for (i, a) in squares.iter().enumerate() {
+                    for b in &squares[i + 1..] {
+                        let side = (a.area.max(b.area) as f64).sqrt() + 2.0;
+                        let distance = (a.x - b.x).hypot(a.y - b.y);
+                        if distance > 1.15 * side && distance < 1.7 * side {
+                            corners.push(((a.x + b.x) / 2.0, (a.y + b.y) / 2.0));
+                            radius += side;
+                        }
+                    }
+                }
+                if corners.len() != columns * rows {
+                    return None;
+                }
+
+                let radius = ((radius / corners.len() as f64 / 4.0) as isize).max(2);
+                corners
+                    .into_iter()
+                    .map(|p| refine(&luma, width, height, p, radius))
+                    .collect::<Vec<_>>()
+            }
+            Pattern::DotGrid { .. } => {
+                let dots = typical(blobs(&dark, width, height, true));
+                if dots.len() != columns * rows {
+                    return None;
+                }
+                dots.iter().map(|dot| (dot.x, dot.y)).collect()
+            }
+        };
+
+        order(&points, columns, rows)
+    }
+}
+
+/// Connected region of dark pixels
+#[derive(Debug, Copy, Clone)]
+struct Blob {
+    /// Number of pixels
+    area: usize,
+    /// Centroid
+    x: f64,
+    y: f64,
+}
+
+/// Returns the threshold separating dark and light pixels with Otsu's method
+fn otsu(luma: &[u8]) -> u8 {
+    let mut histogram = [0u64; 256];
+    for &v in luma {
+        histogram[v as usize] += 1;
+    }
+
+    let total = luma.len() as f64;
+    let sum: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(v, &n)| v as f64 * n as f64)
+        .sum();
+
+    let (mut threshold, mut best) = (0, 0.0);
+    let (mut weight, mut partial) = (0.0, 0.0);
+    for (v, &n) in histogram.iter().enumerate() {
+        weight += n as f64;
+        partial += v as f64 * n as f64;
+        if weight == 0.0 || weight == total {
+            continue;
+        }
+
+        let dark = partial / weight;
+        let light = (sum - partial) / (total - weight);
+        let variance = weight * (total - weight) * (dark - light).powi(2);
+        if variance > best {
+            best = variance;
+            threshold = v;
+        }
+    }
+    threshold as u8
+}
+
+/// Keeps pixels whose whole 3x3 neighbourhood is set
+fn erode(mask: &[bool], width: usize, height: usize) -> Vec<bool> {
+    let mut eroded = vec![false; mask.len()];
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width.saturating_sub(1) {
+            eroded[y * width + x] =
+                (y - 1..=y + 1).all(|y| (x - 1..=x + 1).all(|x| mask[y * width + x]));
+        }
+    }
+    eroded
+}
+
+/// Returns the connected regions of a mask that do not touch the frame border
+fn blobs(mask: &[bool], width: usize, height: usize, diagonal: bool) -> Vec<Blob> {
+    let mut visited = vec![false; mask.len()];
+    let mut blobs = Vec::new();
+    let mut stack = Vec::new();
+
+    for start in 0..mask.len() {
+        if !mask[start] || visited[start] {
+            continue;
+        }
+
+        visited[start] = true;
+        stack.push(start);
+        let (mut area, mut sx, mut sy, mut border) = (0, 0.0, 0.0, false);
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i % width, i / width);
+            area += 1;
+            sx += x as f64;
+            sy += y as f64;
+            if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                border = true;
+                continue;
+            }
+
+            for dy in -1isize..=1 {
+                for dx in -1isize..=1 {
+                    if (dx == 0 && dy == 0) || (!diagonal && dx != 0 && dy != 0) {
+                        continue;
+                    }
+                    let j = (y as isize + dy) as usize * width + (x as isize + dx) as usize;
+                    if mask[j] && !visited[j] {
+                        visited[j] = true;
+                        stack.push(j);
+                    }
+                }
+            }
+        }
+
+        if !border {
+            blobs.push(Blob {
+                area,
+                x: sx / area as f64,
+                y: sy / area as f64,
+            });
+        }
+    }
+    blobs
+}
+
+/// Drops specks and blobs much larger or smaller than the median one
+fn typical(mut blobs: Vec<Blob>) -> Vec<Blob> {
+    blobs.retain(|blob| blob.area >= 4);
+    if blobs.is_empty() {
+        return blobs;
+    }
+
+    let mut areas: Vec<usize> = blobs.iter().map(|blob| blob.area).collect();
+    areas.sort_unstable();
+    let median = areas[areas.len() / 2];
+    blobs.retain(|blob| blob.area * 3 >= median && blob.area <= median * 3);
+    blobs
+}
+
+/// Moves a corner estimate to the saddle point of the surrounding intensity
+///
+/// The gradient at every pixel around a corner is orthogonal to the vector from the corner to
+/// that pixel, the corner is the least squares solution of that condition.
+fn refine(luma: &[u8], width: usize, height: usize, point: Point, radius: isize) -> Point {
+    let at = |x: isize, y: isize| luma[y as usize * width + x as usize] as f64;
+    let (mut qx, mut qy) = point;
+
+    for _ in 0..20 {
+        let (cx, cy) = (qx.round() as isize, qy.round() as isize);
+        if cx - radius < 1
+            || cy - radius < 1
+            || cx + radius >= width as isize - 1
+            || cy + radius >= height as isize - 1
+        {
+            return point;
+        }
+
+        let (mut gxx, mut gxy, mut gyy, mut bx, mut by) = (0.0, 0.0, 0.0, 0.0, 0.0);
+        for y in cy - radius..=cy + radius {
+            for x in cx - radius..=cx + radius {
+                let gx = (at(x + 1, y) - at(x - 1, y)) / 2.0;
+                let gy = (at(x, y + 1) - at(x, y - 1)) / 2.0;
+                gxx += gx * gx;
+                gxy += gx * gy;
+                gyy += gy * gy;
+                bx += gx * gx * x as f64 + gx * gy * y as f64;
+                by += gx * gy * x as f64 + gy * gy * y as f64;
+            }
+        }
+
+        let det = gxx * gyy - gxy * gxy;
+        if det.abs() < 1e-9 {
+            return point;
+        }
+        let (nx, ny) = ((gyy * bx - gxy * by) / det, (gxx * by - gxy * bx) / det);
+        let step = (nx - qx).hypot(ny - qy);
+        qx = nx;
+        qy = ny;
+        if step < 0.01 {
+            break;
+        }
+    }
+
+    if (qx - point.0).hypot(qy - point.1) > radius as f64 {
+        return point;
+    }
+    (qx, qy)
+}
+
+/// Sorts detected points into the row by row order of the pattern
+///
+/// The outermost points are matched to the corners of the grid, all points are then mapped
+/// into grid coordinates through the resulting homography and have to land on distinct cells.
+fn order(points: &[Point], columns: usize, rows: usize) -> Option<Vec<Point>> {
+    let extreme = |key: &dyn Fn(&Point) -> f64| {
+        *points
+            .iter()
+            .max_by(|a, b| key(a).total_cmp(&key(b)))
+            .unwrap()
+    };
+    // Clockwise from top left
+    let image = [
+        extreme(&|p| -p.0 - p.1),
+        extreme(&|p| p.0 - p.1),
+        extreme(&|p| p.0 + p.1),
+        extreme(&|p| p.1 - p.0),
+    ];
+    let (c, r) = ((columns - 1) as f64, (rows - 1) as f64);
+    let grid = [(0.0, 0.0), (c, 0.0), (c, r), (0.0, r)];
+
+    // Try the four rotations of the grid, mirrored assignments are not valid views
+    'rotation: for k in 0..4 {
+        let target: Vec<Point> = (0..4).map(|i| grid[(i + k) % 4]).collect();
+        let h = match homography(&image, &target) {
+            Some(h) => h,
+            None => continue,
+        };
+
+        let mut ordered = vec![None; columns * rows];
+        for &p in points {
+            let (gx, gy) = transform(&h, p);
+            let (x, y) = (gx.round(), gy.round());
+            if (gx - x).abs() > 0.3 || (gy - y).abs() > 0.3 || x < 0.0 || y < 0.0 || x > c || y > r
+            {
+                continue 'rotation;
+            }
+            let slot = &mut ordered[y as usize * columns + x as usize];
+            if slot.is_some() {
+                continue 'rotation;
+            }
+            *slot = Some(p);
+        }
+        return ordered.into_iter().collect();
+    }
+    None
+}
+
+/// Applies a homography to a point
+fn transform(h: &Mat3, p: Point) -> Point {
+    let w = h[2][0] * p.0 + h[2][1] * p.1 + h[2][2];
+    (
+        (h[0][0] * p.0 + h[0][1] * p.1 + h[0][2]) / w,
+        (h[1][0] * p.0 + h[1][1] * p.1 + h[1][2]) / w,
+    )
+}
+
+/// Returns the similarity that moves points to the origin at an average distance of √2
+fn normalization(points: &[Point]) -> Mat3 {
+    let n = points.len() as f64;
+    let (mx, my) = points
+        .iter()
+        .fold((0.0, 0.0), |(x, y), p| (x + p.0 / n, y + p.1 / n));
+    let distance: f64 = points
+        .iter()
+        .map(|p| (p.0 - mx).hypot(p.1 - my))
+        .sum::<f64>()
+        / n;
+    let s = if distance > 0.0 {
+        std::f64::consts::SQRT_2 / distance
+    } else {
+        1.0
+    };
+    [[s, 0.0, -s * mx], [0.0, s, -s * my], [0.0, 0.0, 1.0]]
+}
+
+/// Estimates the homography mapping `from` onto `to` with the normalized DLT
+fn homography(from: &[Point], to: &[Point]) -> Option<Mat3> {
+    if from.len() < 4 || from.len() != to.len() {
+        return None;
+    }
+
+    let (tf, tt) = (normalization(from), normalization(to));
+    let mut ata = vec![0.0; 81];
+    for (&p, &q) in from.iter().zip(to) {
+        let (x, y) = transform(&tf, p);
+        let (u, v) = transform(&tt, q);
+        let rows = [
+            [-x, -y, -1.0, 0.0, 0.0, 0.0, u * x, u * y, u],
+            [0.0, 0.0, 0.0, -x, -y, -1.0, v * x, v * y, v],
+        ];
+        for row in &rows {
+            for i in 0..9 {
+                for j in 0..9 {
+                    ata[i * 9 + j] += row[i] * row[j];
+                }
+            }
+        }
+    }
+
+    let h = smallest_eigenvector(ata, 9);
+    let hn = [[h[0], h[1], h[2]], [h[3], h[4], h[5]], [h[6], h[7], h[8]]];
+    let h = mul(&mul(&invert(&tt)?, &hn), &tf);
+    if h[2][2].abs() < 1e-12 {
+        return None;
+    }
+    let s = 1.0 / h[2][2];
+    Some([
+        [h[0][0] * s, h[0][1] * s, h[0][2] * s],
+        [h[1][0] * s, h[1][1] * s, h[1][2] * s],
+        [h[2][0] * s, h[2][1] * s, 1.0],
+    ])
+}
+
+fn mul(a: &Mat3, b: &Mat3) -> Mat3 {
+    let mut c = [[0.0; 3]; 3];
+    for (i, row) in c.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    c
+}
+
+fn invert(m: &Mat3) -> Option<Mat3> {
+    let cofactor =
+        |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let det = m[0][0] * cofactor(1, 2, 1, 2) - m[0][1] * cofactor(1, 2, 0, 2)
+        + m[0][2] * cofactor(1, 2, 0, 1);
+    if det.abs() < 1e-300 {
+        return None;
+    }
+    let d = 1.0 / det;
+    Some([
+        [
+            cofactor(1, 2, 1, 2) * d,
+            -cofactor(0, 2, 1, 2) * d,
+            cofactor(0, 1, 1, 2) * d,
+        ],
+        [
+            -cofactor(1, 2, 0, 2) * d,
+            cofactor(0, 2, 0, 2) * d,
+            -cofactor(0, 1, 0, 2) * d,
+        ],
+        [
+            cofactor(1, 2, 0, 1) * d,
+            -cofactor(0, 2, 0, 1) * d,
+            cofactor(0, 1, 0, 1) * d,
+        ],
+    ])
+}
+
+/// Returns the eigenvector of the smallest eigenvalue of a symmetric `n`x`n` matrix
+///
+/// Uses cyclic Jacobi rotations, which is plenty for the small systems solved here.
+fn smallest_eigenvector(mut a: Vec<f64>, n: usize) -> Vec<f64> {
+    let mut v = vec![0.0; n * n];
+    for i in 0..n {
+        v[i * n + i] = 1.0;
+    }
+
+    for _ in 0..100 {
+        let off: f64 = (0..n)
+            .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
+            .map(|(i, j)| a[i * n + j].powi(2))
+            .sum();
+        let diagonal: f64 = (0..n).map(|i| a[i * n + i].powi(2)).sum();
+        if off <= 1e-30 * diagonal.max(1e-300) {
+            break;
+        }
+
+        for p in 0..n {
+            for q in p + 1..n {
+                let apq = a[p * n + q];
+                if apq == 0.0 {
+                    continue;
+                }
+                let theta = (a[q * n + q] - a[p * n + p]) / (2.0 * apq);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+
+                for k in 0..n {
+                    let (akp, akq) = (a[k * n + p], a[k * n + q]);
+                    a[k * n + p] = c * akp - s * akq;
+                    a[k * n + q] = s * akp + c * akq;
+                }
+                for k in 0..n {
+                    let (apk, aqk) = (a[p * n + k], a[q * n + k]);
+                    a[p * n + k] = c * apk - s * aqk;
+                    a[q * n + k] = s * apk + c * aqk;
+                }
+                for k in 0..n {
+                    let (vkp, vkq) = (v[k * n + p], v[k * n + q]);
+                    v[k * n + p] = c * vkp - s * vkq;
+                    v[k * n + q] = s * vkp + c * vkq;
+                }
+            }
+        }
+    }
+
+    let min = (0..n)
+        .min_by(|&i, &j| a[i * n + i].total_cmp(&a[j * n + j]))
+        .unwrap_or(0);
+    (0..n).map(|k| v[k * n + min]).collect()
+}
+
+/// Solves `a x = b` for a square system by Gaussian elimination with partial pivoting
+fn solve(mut a: Vec<f64>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    for col in 0..n {
+        let pivot =
+            (col..n).max_by(|&i, &j| a[i * n + col].abs().total_cmp(&a[j * n + col].abs()))?;
+        if a[pivot * n + col].abs() < 1e-300 {
+            return None;
+        }
+        if pivot != col {
+            for k in 0..n {
+                a.swap(pivot * n + k, col * n + k);
+            }
+            b.swap(pivot, col);
+        }
+
+        for row in col + 1..n {
+            let f = a[row * n + col] / a[col * n + col];
+            if f == 0.0 {
+                continue;
+            }
+            for k in col..n {
+                a[row * n + k] -= f * a[col * n + k];
+            }
+            b[row] -= f * b[col];
+        }
+    }
+
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| a[row * n + k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row * n + row];
+    }
+    Some(x)
+}
+
+/// Converts a rotation vector into a rotation matrix
+fn rotation_matrix(r: &[f64]) -> Mat3 {
+    let angle = (r[0] * r[0] + r[1] * r[1] + r[2] * r[2]).sqrt();
+    if angle < 1e-12 {
+        return [[1.0, -r[2], r[1]], [r[2], 1.0, -r[0]], [-r[1], r[0], 1.0]];
+    }
+
+    let (x, y, z) = (r[0] / angle, r[1] / angle, r[2] / angle);
+    let (s, c) = angle.sin_cos();
+    let t = 1.0 - c;
+    [
+        [t * x * x + c, t * x * y - s * z, t * x * z + s * y],
+        [t * x * y + s * z, t * y * y + c, t * y * z - s * x],
+        [t * x * z - s * y, t * y * z + s * x, t * z * z + c],
+    ]
+}
+
+/// Converts a rotation matrix into a rotation vector
+fn rotation_vector(m: &Mat3) -> [f64; 3] {
+    let axis = [m[2][1] - m[1][2], m[0][2] - m[2][0], m[1][0] - m[0][1]];
+    let s = (axis[0].powi(2) + axis[1].powi(2) + axis[2].powi(2)).sqrt() / 2.0;
+    let c = ((m[0][0] + m[1][1] + m[2][2] - 1.0) / 2.0).clamp(-1.0, 1.0);
+    let angle = s.atan2(c);
+
+    if s > 1e-7 {
+        let f = angle / (2.0 * s);
+        return [axis[0] * f, axis[1] * f, axis[2] * f];
+    }
+    if c > 0.0 {
+        return [0.0; 3];
+    }
+
+    // Half turn, the axis is the column of R + I with the largest norm
+    let column = (0..3).max_by(|&i, &j| m[i][i].total_cmp(&m[j][j])).unwrap();
+    let mut axis = [m[0][column], m[1][column], m[2][column]];
+    axis[column] += 1.0;
+    let norm = (axis[0].powi(2) + axis[1].powi(2) + axis[2].powi(2)).sqrt();
+    [
+        axis[0] / norm * angle,
+        axis[1] / norm * angle,
+        axis[2] / norm * angle,
+    ]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Camera intrinsics and lens distortion
+///
+/// Uses the pinhole model with radial and tangential distortion, with the coefficients in the
+/// order `k1, k2, p1, p2, k3` common to most calibration tools.
+pub struct Calibration {
+    /// Width of the calibrated frames
+    pub width: u32,
+    /// Height of the calibrated frames
+    pub height: u32,
+    /// Horizontal focal length in pixels
+    pub fx: f64,
+    /// Vertical focal length in pixels
+    pub fy: f64,
+    /// Horizontal principal point in pixels
+    pub cx: f64,
+    /// Vertical principal point in pixels
+    pub cy: f64,
+    /// Distortion coefficients `k1, k2, p1, p2, k3`
+    pub distortion: [f64; 5],
+    /// Root mean square reprojection error of the calibration in pixels
+    pub rms: f64,
+}
+
+impl Calibration {
+    /// Returns a calibration without distortion
+    ///
+    /// The focal length is set to the larger frame dimension and the principal point to the
+    /// frame center.
+    pub fn new(width: u32, height: u32) -> Self {
+        let f = width.max(height) as f64;
+        Calibration {
+            width,
+            height,
+            fx: f,
+            fy: f,
+            cx: (width as f64 - 1.0) / 2.0,
+            cy: (height as f64 - 1.0) / 2.0,
+            distortion: [0.0; 5],
+            rms: 0.0,
+        }
+    }
+
+    /// Applies the lens distortion to normalized image coordinates
+    fn distort_normalized(&self, x: f64, y: f64) -> Point {
+        let [k1, k2, p1, p2, k3] = self.distortion;
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (k1 + r2 * (k2 + r2 * k3));
+        (
+            x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x),
+            y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y,
+        )
+    }
+
+    /// Returns where a pixel of the undistorted image is found in the captured image
+    pub fn distort_point(&self, point: Point) -> Point {
+        let (x, y) = ((point.0 - self.cx) / self.fx, (point.1 - self.cy) / self.fy);
+        let (x, y) = self.distort_normalized(x, y);
+        (x * self.fx + self.cx, y * self.fy + self.cy)
+    }
+
+    /// Returns where a pixel of the captured image ends up in the undistorted image
+    ///
+    /// Use this to correct detected features without remapping the whole frame.
+    pub fn undistort_point(&self, point: Point) -> Point {
+        let (xd, yd) = ((point.0 - self.cx) / self.fx, (point.1 - self.cy) / self.fy);
+
+        // Fixed point iteration, converges quickly for the moderate distortion of real lenses
+        let (mut x, mut y) = (xd, yd);
+        for _ in 0..20 {
+            let (dx, dy) = self.distort_normalized(x, y);
+            x -= dx - xd;
+            y -= dy - yd;
+        }
+        (x * self.fx + self.cx, y * self.fy + self.cy)
+    }
+
+    /// Precomputes the lookup table that undistorts frames of the calibrated size
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::calibration::Calibration;
+    /// use openpnp_capture::Frame;
+    /// use std::time::Instant;
+    ///
+    /// let frame = Frame {
+    ///     width: 2,
+    ///     height: 2,
+    ///     sequence: 0,
+    ///     timestamp: Instant::now(),
+    ///     data: (0..12).collect(),
+    /// };
+    ///
+    /// // Without distortion every pixel stays in place
+    /// let remap = Calibration::new(2, 2).remap();
+    /// assert_eq!(remap.apply(&frame).unwrap().data, frame.data);
+    /// ```
+    pub fn remap(&self) -> Remap {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut taps = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = self.distort_point((x as f64, y as f64));
+                taps.push(Tap::new(sx, sy, width, height));
+            }
+        }
+
+        Remap {
+            width: self.width,
+            height: self.height,
+            taps,
+        }
+    }
+
+    /// Returns the file a calibration is stored in for a device unique ID
+    #[cfg(feature = "profile")]
+    fn path(dir: &Path, unique_id: &str) -> PathBuf {
+        dir.join(format!("{}.toml", profile::file_name(unique_id)))
+    }
+
+    /// Loads the calibration of a device from a directory
+    ///
+    /// `unique_id` is the [`crate::Device::id`] of the camera.
+    #[cfg(feature = "profile")]
+    pub fn load<P: AsRef<Path>>(dir: P, unique_id: &str) -> io::Result<Self> {
+        let s = fs::read_to_string(Calibration::path(dir.as_ref(), unique_id))?;
+        toml::from_str(&s).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Saves the calibration of a device into a directory, creating it if needed
+    #[cfg(feature = "profile")]
+    pub fn save<P: AsRef<Path>>(&self, dir: P, unique_id: &str) -> io::Result<()> {
+        let s = toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::create_dir_all(dir.as_ref())?;
+        fs::write(Calibration::path(dir.as_ref(), unique_id), s)
+    }
+}
+
+/// Number of intrinsic parameters: focal lengths, principal point and distortion
+const INTRINSICS: usize = 9;
+
+/// Projects a point of the target with the intrinsics and one view pose
+fn project(intrinsics: &[f64], pose: &[f64], point: Point) -> Point {
+    let r = rotation_matrix(&pose[..3]);
+    let (px, py) = point;
+    let x = r[0][0] * px + r[0][1] * py + pose[3];
+    let y = r[1][0] * px + r[1][1] * py + pose[4];
+    let z = r[2][0] * px + r[2][1] * py + pose[5];
+
+    let [k1, k2, p1, p2, k3] = [
+        intrinsics[4],
+        intrinsics[5],
+        intrinsics[6],
+        intrinsics[7],
+        intrinsics[8],
+    ];
+    let (x, y) = (x / z, y / z);
+    let r2 = x * x + y * y;
+    let radial = 1.0 + r2 * (k1 + r2 * (k2 + r2 * k3));
+    let xd = x * radial + 2.0 * p1 * x * y + p2 * (r2 + 2.0 * x * x);
+    let yd = y * radial + p1 * (r2 + 2.0 * y * y) + 2.0 * p2 * x * y;
+    (
+        intrinsics[0] * xd + intrinsics[2],
+        intrinsics[1] * yd + intrinsics[3],
+    )
+}
+
+/// Returns the reprojection errors of one view, two values per point
+fn residuals(intrinsics: &[f64], pose: &[f64], object: &[Point], image: &[Point]) -> Vec<f64> {
+    object
+        .iter()
+        .zip(image)
+        .flat_map(|(&o, &i)| {
+            let (u, v) = project(intrinsics, pose, o);
+            vec![u - i.0, v - i.1]
+        })
+        .collect()
+}
+
+/// Estimates the focal lengths from the view homographies, assuming a centered principal point
+///
+/// Each homography constrains the image of the absolute conic, see Zhang, "A Flexible New
+/// Technique for Camera Calibration".
+fn initial_focal(homographies: &[Mat3], cx: f64, cy: f64) -> Option<(f64, f64)> {
+    let (mut a, mut b) = ([0.0; 4], [0.0; 2]);
+    for h in homographies {
+        let h = mul(&[[1.0, 0.0, -cx], [0.0, 1.0, -cy], [0.0, 0.0, 1.0]], h);
+        let (h1, h2) = ([h[0][0], h[1][0], h[2][0]], [h[0][1], h[1][1], h[2][1]]);
+        let rows = [
+            ([h1[0] * h2[0], h1[1] * h2[1]], -h1[2] * h2[2]),
+            (
+                [h1[0].powi(2) - h2[0].powi(2), h1[1].powi(2) - h2[1].powi(2)],
+                h2[2].powi(2) - h1[2].powi(2),
+            ),
+        ];
+        for (row, rhs) in &rows {
+            a[0] += row[0] * row[0];
+            a[1] += row[0] * row[1];
+            a[3] += row[1] * row[1];
+            b[0] += row[0] * rhs;
+            b[1] += row[1] * rhs;
+        }
+    }
+    a[2] = a[1];
+
+    let x = solve(a.to_vec(), b.to_vec())?;
+    if x[0] > 0.0 && x[1] > 0.0 {
+        Some((1.0 / x[0].sqrt(), 1.0 / x[1].sqrt()))
+    } else {
+        None
+    }
+}
+
+/// Returns the pose of a view from its homography and the camera intrinsics
+fn initial_pose(h: &Mat3, fx: f64, fy: f64, cx: f64, cy: f64) -> [f64; 6] {
+    let back = |c: usize| {
+        [
+            (h[0][c] - cx * h[2][c]) / fx,
+            (h[1][c] - cy * h[2][c]) / fy,
+            h[2][c],
+        ]
+    };
+    let norm = |v: &[f64; 3]| (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    let (r1, r2, t) = (back(0), back(1), back(2));
+
+    // The homography is only known up to scale, the target has to be in front of the camera
+    let mut scale = 2.0 / (norm(&r1) + norm(&r2));
+    if t[2] < 0.0 {
+        scale = -scale;
+    }
+
+    let r1 = r1.map(|v| v * scale);
+    let r1 = r1.map(|v| v / norm(&r1));
+    let r2 = r2.map(|v| v * scale);
+    let dot = r1[0] * r2[0] + r1[1] * r2[1] + r1[2] * r2[2];
+    let r2 = [
+        r2[0] - dot * r1[0],
+        r2[1] - dot * r1[1],
+        r2[2] - dot * r1[2],
+    ];
+    let r2 = r2.map(|v| v / norm(&r2));
+    let r3 = [
+        r1[1] * r2[2] - r1[2] * r2[1],
+        r1[2] * r2[0] - r1[0] * r2[2],
+        r1[0] * r2[1] - r1[1] * r2[0],
+    ];
+    let r = rotation_vector(&[
+        [r1[0], r2[0], r3[0]],
+        [r1[1], r2[1], r3[1]],
+        [r1[2], r2[2], r3[2]],
+    ]);
+    [r[0], r[1], r[2], t[0] * scale, t[1] * scale, t[2] * scale]
+}
+
+/// Estimates camera intrinsics and lens distortion from views of a pattern
+///
+/// `views` holds the image points of each view as returned by [`Pattern::detect`], taken from
+/// frames of `width` x `height` pixels. At least [`MIN_VIEWS`] views are required, the target
+/// should be tilted differently and cover different parts of the frame in each of them.
+///
+/// The intrinsics are initialized in closed form from the view homographies and then refined
+/// together with the distortion and the view poses by Levenberg-Marquardt minimization of the
+/// reprojection error. Views with points that are not finite are rejected.
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::calibration::{calibrate, Calibration, Pattern};
+///
+/// let truth = Calibration {
+///     fx: 610.0,
+///     fy: 600.0,
+///     cx: 322.0,
+///     cy: 236.0,
+///     distortion: [-0.2, 0.05, 0.001, -0.0005, 0.0],
+///     ..Calibration::new(640, 480)
+/// };
+/// let pattern = Pattern::Checkerboard { columns: 11, rows: 8, square: 25.0 };
+///
+/// // Tilts the target about its center, moves it in front of the camera and projects it
+/// let view = |ax: f64, ay: f64, tx: f64, ty: f64, tz: f64| -> Vec<(f64, f64)> {
+///     let project = |(x, y): (f64, f64)| {
+///         let (x, y) = (x - 125.0, y - 87.5);
+///         let (y, z) = (y * ax.cos(), y * ax.sin());
+///         let (x, z) = (x * ay.cos() + z * ay.sin(), z * ay.cos() - x * ay.sin());
+///         let (x, y, z) = (x + tx, y + ty, z + tz);
+///         truth.distort_point((truth.fx * x / z + truth.cx, truth.fy * y / z + truth.cy))
+///     };
+///     pattern.object_points().into_iter().map(project).collect()
+/// };
+/// let views = vec![
+///     view(0.0, 0.0, 0.0, 0.0, 500.0),
+///     view(0.4, 0.0, -60.0, 40.0, 520.0),
+///     view(-0.35, 0.2, 70.0, -50.0, 480.0),
+///     view(0.1, -0.4, -80.0, -60.0, 550.0),
+///     view(-0.2, 0.35, 60.0, 60.0, 530.0),
+/// ];
+///
+/// let calibration = calibrate(&pattern, &views, 640, 480).unwrap();
+/// assert!(calibration.rms < 0.1);
+/// assert!((calibration.fx - truth.fx).abs() < 0.5 && (calibration.fy - truth.fy).abs() < 0.5);
+/// assert!((calibration.cx - truth.cx).abs() < 0.5 && (calibration.cy - truth.cy).abs() < 0.5);
+/// for (k, t) in calibration.distortion.iter().zip(&truth.distortion).take(4) {
+///     assert!((k - t).abs() < 1e-3);
+/// }
+///
+/// let mut broken = views.clone();
+/// broken[2][7].0 = f64::NAN;
+/// assert!(calibrate(&pattern, &broken, 640, 480).is_err());
+/// ```
+pub fn calibrate(
+    pattern: &Pattern,
+    views: &[Vec<Point>],
+    width: u32,
+    height: u32,
+) -> io::Result<Calibration> {
+    let object = pattern.object_points();
+    if views.len() < MIN_VIEWS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("need at least {} views", MIN_VIEWS),
+        ));
+    }
+    if views.iter().any(|view| view.len() != object.len()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "view does not match pattern",
+        ));
+    }
+    if views
+        .iter()
+        .flatten()
+        .any(|point| !point.0.is_finite() || !point.1.is_finite())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "view contains points that are not finite",
+        ));
+    }
+
+    let homographies = views
+        .iter()
+        .map(|view| homography(&object, view))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| io::Error::other("degenerate view"))?;
+
+    let initial = Calibration::new(width, height);
+    let (fx, fy) =
+        initial_focal(&homographies, initial.cx, initial.cy).unwrap_or((initial.fx, initial.fy));
+    let mut params = vec![fx, fy, initial.cx, initial.cy, 0.0, 0.0, 0.0, 0.0, 0.0];
+    for h in &homographies {
+        params.extend_from_slice(&initial_pose(h, fx, fy, initial.cx, initial.cy));
+    }
+
+    let n = params.len();
+    let cost = |params: &[f64]| -> f64 {
+        views
+            .iter()
+            .enumerate()
+            .map(|(i, view)| {
+                let pose = &params[INTRINSICS + 6 * i..INTRINSICS + 6 * i + 6];
+                residuals(&params[..INTRINSICS], pose, &object, view)
+                    .iter()
+                    .map(|r| r * r)
+                    .sum::<f64>()
+            })
+            .sum()
+    };
+
+    let mut current = cost(&params);
+    let mut lambda = 1e-3;
+    for _ in 0..100 {
+        // Normal equations, every view only depends on the intrinsics and its own pose
+        let mut jtj = vec![0.0; n * n];
+        let mut jtr = vec![0.0; n];
+        for (i, view) in views.iter().enumerate() {
+            let columns: Vec<usize> = (0..INTRINSICS)
+                .chain(INTRINSICS + 6 * i..INTRINSICS + 6 * i + 6)
+                .collect();
+            let r = residuals(
+                &params[..INTRINSICS],
+                &params[INTRINSICS + 6 * i..INTRINSICS + 6 * i + 6],
+                &object,
+                view,
+            );
+
+            let jacobian: Vec<Vec<f64>> = columns
+                .iter()
+                .map(|&c| {
+                    let step = 1e-6 * params[c].abs().max(1.0);
+                    let mut plus = params.clone();
+                    let mut minus = params.clone();
+                    plus[c] += step;
+                    minus[c] -= step;
+                    let pose = INTRINSICS + 6 * i..INTRINSICS + 6 * i + 6;
+                    let rp = residuals(&plus[..INTRINSICS], &plus[pose.clone()], &object, view);
+                    let rm = residuals(&minus[..INTRINSICS], &minus[pose], &object, view);
+                    rp.iter()
+                        .zip(&rm)
+                        .map(|(p, m)| (p - m) / (2.0 * step))
+                        .collect()
+                })
+                .collect();
+
+            for (a, ja) in columns.iter().zip(&jacobian) {
+                jtr[*a] += ja.iter().zip(&r).map(|(j, r)| j * r).sum::<f64>();
+                for (b, jb) in columns.iter().zip(&jacobian) {
+                    jtj[a * n + b] += ja.iter().zip(jb).map(|(x, y)| x * y).sum::<f64>();
+                }
+            }
+        }
+
+        let mut improved = false;
+        while lambda < 1e12 {
+            let mut a = jtj.clone();
+            for k in 0..n {
+                a[k * n + k] += lambda * jtj[k * n + k].max(1e-12);
+            }
+            let step = match solve(a, jtr.iter().map(|v| -v).collect()) {
+                Some(step) => step,
+                None => {
+                    lambda *= 10.0;
+                    continue;
+                }
+            };
+
+            let candidate: Vec<f64> = params.iter().zip(&step).map(|(p, s)| p + s).collect();
+            let next = cost(&candidate);
+            if next.is_finite() && next < current {
+                let gain = (current - next) / current.max(1e-300);
+                params = candidate;
+                current = next;
+                lambda = (lambda / 10.0).max(1e-12);
+                improved = gain > 1e-12;
+                break;
+            }
+            lambda *= 10.0;
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    Ok(Calibration {
+        width,
+        height,
+        fx: params[0],
+        fy: params[1],
+        cx: params[2],
+        cy: params[3],
+        distortion: [params[4], params[5], params[6], params[7], params[8]],
+        rms: (current / (views.len() * object.len()) as f64).sqrt(),
+    })
+}
+
+#[derive(Debug, Clone)]
+/// Collects views of a pattern from captured frames
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::calibration::{Calibrator, Pattern};
+/// use openpnp_capture::{Device, Format, Stream};
+///
+/// let pattern = Pattern::Checkerboard { columns: 9, rows: 6, square: 5.0 };
+/// let mut calibrator = Calibrator::new(pattern);
+///
+/// let dev = Device::new(0);
+/// if let Some(dev) = &dev {
+///     if let Some(mut stream) = Stream::new(&dev, &Format::default()) {
+///         while calibrator.views() < 15 {
+///             // Move the target between frames
+///             stream.advance();
+///             if let Ok(frame) = stream.read_frame() {
+///                 calibrator.add(&frame);
+///             }
+///         }
+///         let calibration = calibrator.calibrate().unwrap();
+///         stream.set_undistortion(Some(calibration.remap())).unwrap();
+///     }
+/// }
+/// ```
+pub struct Calibrator {
+    pattern: Pattern,
+    size: Option<(u32, u32)>,
+    views: Vec<Vec<Point>>,
+}
+
+impl Calibrator {
+    /// Returns a calibrator for the given pattern
+    pub fn new(pattern: Pattern) -> Self {
+        Calibrator {
+            pattern,
+            size: None,
+            views: Vec::new(),
+        }
+    }
+
+    /// Looks for the pattern in a frame and keeps the view if it was found
+    ///
+    /// Returns false if the pattern was not found or the frame size differs from the first one.
+    pub fn add(&mut self, frame: &Frame) -> bool {
+        let size = (frame.width, frame.height);
+        if matches!(self.size, Some(s) if s != size) {
+            return false;
+        }
+
+        match self.pattern.detect(frame) {
+            Some(points) => {
+                self.size = Some(size);
+                self.views.push(points);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the number of collected views
+    pub fn views(&self) -> usize {
+        self.views.len()
+    }
+
+    /// Estimates the calibration from the collected views, see [`calibrate`]
+    pub fn calibrate(&self) -> io::Result<Calibration> {
+        let (width, height) = self.size.unwrap_or_default();
+        calibrate(&self.pattern, &self.views, width, height)
+    }
+}
+
+/// Bilinear sample of the captured frame, in 8-bit fixed point
+#[derive(Debug, Copy, Clone)]
+struct Tap {
+    /// Index of the top left source pixel, `u32::MAX` if outside the frame
+    index: u32,
+    /// Horizontal weight of the right pixels, 0 to 256
+    fx: u16,
+    /// Vertical weight of the bottom pixels, 0 to 256
+    fy: u16,
+}
+
+impl Tap {
+    fn new(x: f64, y: f64, width: usize, height: usize) -> Self {
+        if width < 2 || height < 2 || x < 0.0 || y < 0.0 {
+            return Tap::OUTSIDE;
+        }
+        if x > (width - 1) as f64 || y > (height - 1) as f64 {
+            return Tap::OUTSIDE;
+        }
+
+        let x0 = (x.floor() as usize).min(width - 2);
+        let y0 = (y.floor() as usize).min(height - 2);
+        Tap {
+            index: (y0 * width + x0) as u32,
+            fx: ((x - x0 as f64) * 256.0).round() as u16,
+            fy: ((y - y0 as f64) * 256.0).round() as u16,
+        }
+    }
+
+    const OUTSIDE: Tap = Tap {
+        index: u32::MAX,
+        fx: 0,
+        fy: 0,
+    };
+}
+
+#[derive(Clone)]
+/// Precomputed undistortion lookup table, see [`Calibration::remap`]
+pub struct Remap {
+    width: u32,
+    height: u32,
+    taps: Vec<Tap>,
+}
+
+impl fmt::Debug for Remap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Remap")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+impl Remap {
+    /// Returns the frame size the table was computed for
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Undistorts a region of an RGB24 frame, pixels mapped from outside the frame are black
+    ///
+    /// `src` must match the table size and `roi` must be clipped to it.
+    pub(crate) fn apply_region(&self, src: &[u8], roi: &Roi, dst: &mut Vec<u8>) {
+        let width = self.width as usize;
+        dst.clear();
+        dst.reserve((roi.width * roi.height * 3) as usize);
+
+        for y in roi.y as usize..(roi.y + roi.height) as usize {
+            let row =
+                &self.taps[y * width + roi.x as usize..y * width + (roi.x + roi.width) as usize];
+            for tap in row {
+                if tap.index == u32::MAX {
+                    dst.extend_from_slice(&[0, 0, 0]);
+                    continue;
+                }
+
+                let (fx, fy) = (tap.fx as u32, tap.fy as u32);
+                let i = tap.index as usize * 3;
+                let j = i + width * 3;
+                for c in 0..3 {
+                    let top = src[i + c] as u32 * (256 - fx) + src[i + 3 + c] as u32 * fx;
+                    let bottom = src[j + c] as u32 * (256 - fx) + src[j + 3 + c] as u32 * fx;
+                    dst.push(((top * (256 - fy) + bottom * fy + 32768) >> 16) as u8);
+                }
+            }
+        }
+    }
+
+    /// Returns an undistorted copy of an RGB24 frame
+    pub fn apply(&self, frame: &Frame) -> io::Result<Frame> {
+        let len = self.width as usize * self.height as usize * 3;
+        if (frame.width, frame.height) != self.size() || frame.data.len() != len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame size does not match calibration",
+            ));
+        }
+
+        let mut data = Vec::new();
+        self.apply_region(&frame.data, &Roi::full(self.width, self.height), &mut data);
+        Ok(Frame {
+            width: frame.width,
+            height: frame.height,
+            sequence: frame.sequence,
+            timestamp: frame.timestamp,
+            data,
+        })
+    }
+}
@@ -0,0 +1,334 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::format::Format;
+use crate::frame::Frame;
+use crate::selector::DeviceSelector;
+use crate::stream::Stream;
+
+/// Default time without new frames after which a camera is considered stalled
+pub const DEFAULT_STALL_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+/// Named stream of a camera set
+struct Camera {
+    name: String,
+    stream: Stream,
+    /// Frame counter at the last health check
+    frames: u32,
+    /// Host time at which the frame counter last changed
+    last_frame: Instant,
+}
+
+impl Camera {
+    /// Samples the frame counter and records when it last changed
+    fn update(&mut self) -> u32 {
+        let frames = self.stream.frame_count();
+        if frames != self.frames {
+            self.frames = frames;
+            self.last_frame = Instant::now();
+        }
+        frames
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+/// Health of a camera in a set
+pub struct Health {
+    /// Camera name
+    pub name: String,
+    /// Value of the stream frame counter
+    pub frames: u32,
+    /// Time since the frame counter last changed
+    pub since_last_frame: Duration,
+    /// No frame arrived within the stall timeout
+    pub stalled: bool,
+}
+
+#[derive(Debug, Clone)]
+/// Frames captured from all cameras of a set, in the order the cameras were added
+pub struct Capture {
+    /// Camera names and their frames
+    pub frames: Vec<(String, Frame)>,
+}
+
+impl Capture {
+    /// Returns the frame of a camera
+    pub fn get(&self, name: &str) -> Option<&Frame> {
+        self.frames
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, frame)| frame)
+    }
+
+    /// Returns the time between the first and the last frame of the capture
+    ///
+    /// Frames are compared by [`Frame::timestamp`], the host time at which each frame was
+    /// received from its device, so the time it took to read the frames does not count. Cameras
+    /// with different transfer or decoding latency, e.g. MJPEG and uncompressed streams, show a
+    /// constant offset on top of the actual exposure skew.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::camera_set::Capture;
+    /// use openpnp_capture::Frame;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let frame = |timestamp| Frame {
+    ///     width: 0,
+    ///     height: 0,
+    ///     sequence: 0,
+    ///     timestamp,
+    ///     data: Vec::new(),
+    /// };
+    /// let now = Instant::now();
+    /// let capture = Capture {
+    ///     frames: vec![
+    ///         ("top".to_string(), frame(now + Duration::from_millis(12))),
+    ///         ("bottom".to_string(), frame(now)),
+    ///     ],
+    /// };
+    /// assert_eq!(capture.skew(), Duration::from_millis(12));
+    /// assert_eq!(capture.offset("top"), Some(Duration::from_millis(12)));
+    /// ```
+    pub fn skew(&self) -> Duration {
+        let timestamps = self.frames.iter().map(|(_, frame)| frame.timestamp);
+        match (timestamps.clone().min(), timestamps.max()) {
+            (Some(first), Some(last)) => last - first,
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// Returns how much later the frame of a camera was received than the first frame, see
+    /// [`Capture::skew`]
+    pub fn offset(&self, name: &str) -> Option<Duration> {
+        let first = self.frames.iter().map(|(_, frame)| frame.timestamp).min()?;
+        self.get(name).map(|frame| frame.timestamp - first)
+    }
+}
+
+#[derive(Debug)]
+/// Several cameras captured together
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::camera_set::CameraSet;
+/// use openpnp_capture::{DeviceSelector, Format};
+/// use std::time::{Duration, Instant};
+///
+/// let mut set = CameraSet::new();
+/// let format = Format::default().width(1280).height(720);
+/// let top = set.add("top", &DeviceSelector::new().serial("A1B2C3"), &format);
+/// let bottom = set.add("bottom", &DeviceSelector::new().serial("D4E5F6"), &format);
+/// if top.is_ok() && bottom.is_ok() {
+///     // ... move the head and wait for it to stop ...
+///     let stopped = Instant::now();
+///     if let Ok(capture) = set.capture_all_after(stopped, 0, Duration::from_secs(1)) {
+///         println!("Skew between cameras: {:?}", capture.skew());
+///     }
+/// }
+/// ```
+pub struct CameraSet {
+    cameras: Vec<Camera>,
+    stall_timeout: Duration,
+}
+
+impl Default for CameraSet {
+    fn default() -> Self {
+        CameraSet::new()
+    }
+}
+
+impl CameraSet {
+    /// Returns an empty set
+    pub fn new() -> Self {
+        CameraSet {
+            cameras: Vec::new(),
+            stall_timeout: DEFAULT_STALL_TIMEOUT,
+        }
+    }
+
+    /// Builder: sets the time without new frames after which a camera is considered stalled
+    pub fn stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = timeout;
+        self
+    }
+
+    /// Resolves a selector and adds a stream in the given format under a name
+    pub fn add(
+        &mut self,
+        name: &str,
+        selector: &DeviceSelector,
+        format: &Format,
+    ) -> io::Result<()> {
+        if self.index(name).is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("duplicate camera name: {}", name),
+            ));
+        }
+
+        let dev = selector.resolve()?;
+        let stream = Stream::new(&dev, format)
+            .ok_or_else(|| io::Error::other(format!("failed to open stream on {}", dev.name)))?;
+        self.add_stream(name, stream)
+    }
+
+    /// Adds an already opened stream under a name
+    pub fn add_stream(&mut self, name: &str, stream: Stream) -> io::Result<()> {
+        if self.index(name).is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("duplicate camera name: {}", name),
+            ));
+        }
+
+        self.cameras.push(Camera {
+            name: name.to_string(),
+            frames: stream.frame_count(),
+            stream,
+            last_frame: Instant::now(),
+        });
+        Ok(())
+    }
+
+    /// Removes a camera and returns its stream
+    pub fn remove(&mut self, name: &str) -> Option<Stream> {
+        self.index(name)
+            .map(|index| self.cameras.remove(index).stream)
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        self.cameras.iter().position(|camera| camera.name == name)
+    }
+
+    /// Returns the names of the cameras in the order they were added
+    pub fn names(&self) -> Vec<&str> {
+        self.cameras
+            .iter()
+            .map(|camera| camera.name.as_str())
+            .collect()
+    }
+
+    /// Returns the number of cameras
+    pub fn len(&self) -> usize {
+        self.cameras.len()
+    }
+
+    /// Returns true if the set holds no cameras
+    pub fn is_empty(&self) -> bool {
+        self.cameras.is_empty()
+    }
+
+    /// Returns the stream of a camera
+    pub fn get(&self, name: &str) -> Option<&Stream> {
+        self.index(name).map(|index| &self.cameras[index].stream)
+    }
+
+    /// Returns the stream of a camera for configuration
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Stream> {
+        self.index(name)
+            .map(move |index| &mut self.cameras[index].stream)
+    }
+
+    /// Samples the frame counters and returns the health of every camera
+    ///
+    /// A camera is considered stalled if its frame counter did not change for longer than the
+    /// stall timeout. Call this regularly, e.g. once per machine cycle, since frame arrival is
+    /// only noticed when the counters are sampled.
+    pub fn health(&mut self) -> Vec<Health> {
+        let timeout = self.stall_timeout;
+        self.cameras
+            .iter_mut()
+            .map(|camera| {
+                let frames = camera.update();
+                let since_last_frame = camera.last_frame.elapsed();
+                Health {
+                    name: camera.name.clone(),
+                    frames,
+                    since_last_frame,
+                    stalled: since_last_frame > timeout,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns one frame per camera, each exposed after the given instant
+    ///
+    /// This is [`Stream::capture_after`] for all cameras at once: the cameras are watched in
+    /// parallel and every frame is read as soon as it is complete, so the frames are as close
+    /// in time as the free running cameras allow. Use [`Capture::skew`] to check how far apart
+    /// they are.
+    ///
+    /// # Arguments
+    ///
+    /// * `instant` - Point in time the exposures must start after
+    /// * `settle` - Number of additional frames to skip on every camera
+    /// * `timeout` - Maximum time to wait, counted from the call
+    pub fn capture_all_after(
+        &mut self,
+        instant: Instant,
+        settle: u32,
+        timeout: Duration,
+    ) -> io::Result<Capture> {
+        let deadline = Instant::now() + timeout;
+
+        let now = Instant::now();
+        if instant > now {
+            if instant > deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "no frame before deadline",
+                ));
+            }
+            thread::sleep(instant - now);
+        }
+
+        // The counter is only bumped once a frame is complete, so the frame in flight is
+        // discarded by skipping two
+        let skip = settle.saturating_add(2);
+        let base: Vec<u32> = self
+            .cameras
+            .iter_mut()
+            .map(|camera| camera.update())
+            .collect();
+        let mut frames: Vec<Option<Frame>> = vec![None; self.cameras.len()];
+
+        while frames.iter().any(Option::is_none) {
+            for ((camera, base), slot) in self.cameras.iter_mut().zip(&base).zip(&mut frames) {
+                if slot.is_none() && camera.update().wrapping_sub(*base) >= skip {
+                    *slot = Some(camera.stream.read_frame()?);
+                }
+            }
+
+            if frames.iter().any(Option::is_none) {
+                if Instant::now() >= deadline {
+                    let missing: Vec<&str> = self
+                        .cameras
+                        .iter()
+                        .zip(&frames)
+                        .filter(|(_, frame)| frame.is_none())
+                        .map(|(camera, _)| camera.name.as_str())
+                        .collect();
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("no frame before deadline from {}", missing.join(", ")),
+                    ));
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+        }
+
+        Ok(Capture {
+            frames: self
+                .cameras
+                .iter()
+                .map(|camera| camera.name.clone())
+                .zip(frames.into_iter().flatten())
+                .collect(),
+        })
+    }
+}
@@ -2,6 +2,9 @@ use lazy_static::lazy_static;
 use openpnp_capture_sys as ffi;
 use std::sync::Mutex;
 
+use crate::device::Device;
+use crate::selector::{DeviceSelector, ResolveError};
+
 #[derive(Debug)]
 /// Library context
 pub struct Context {
@@ -24,6 +27,25 @@ impl Drop for Context {
     }
 }
 
+impl Context {
+    /// Returns the single device matching a selector
+    ///
+    /// The error explains which devices were considered and which rule rejected them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::context::Context;
+    /// use openpnp_capture::selector::DeviceSelector;
+    ///
+    /// let selector = DeviceSelector::new().name("^HD Pro Webcam");
+    /// let dev = Context::resolve(&selector);
+    /// ```
+    pub fn resolve(selector: &DeviceSelector) -> Result<Device, ResolveError> {
+        selector.resolve()
+    }
+}
+
 // Required by lazy_static
 unsafe impl Send for Context {}
 
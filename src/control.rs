@@ -0,0 +1,135 @@
+use openpnp_capture_sys as ffi;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of menu values queried per control
+///
+/// Broken drivers may report huge menu ranges, menu values beyond the first `MAX_MENU_ITEMS`
+/// are not listed.
+pub const MAX_MENU_ITEMS: u32 = 256;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+/// Value type of a control
+pub enum Kind {
+    /// Integer in the range `min..=max`
+    Integer,
+    /// 0 or 1
+    Boolean,
+    /// Index into a list of named entries
+    Menu,
+    /// Writing any value triggers an action
+    Button,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Entry of a menu control
+pub struct MenuItem {
+    /// Value to set for this entry
+    pub value: i32,
+    /// Name of the entry
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Camera control as exposed by the driver
+///
+/// Besides the controls covered by [`crate::Property`], this includes e.g. pan/tilt, LEDs and
+/// vendor extension units mapped by the driver. Controls are currently only enumerated on Linux.
+pub struct Control {
+    /// Platform control ID (V4L2 control ID on Linux)
+    pub id: u32,
+    /// Name as reported by the driver, e.g. `Exposure Time, Absolute`
+    pub name: String,
+    /// Value type
+    pub kind: Kind,
+    /// Minimum value
+    pub min: i32,
+    /// Maximum value
+    pub max: i32,
+    /// Distance between valid values, 0 if unknown
+    pub step: i32,
+    /// Default value
+    pub default: i32,
+    /// The control cannot be written
+    pub read_only: bool,
+    /// The control is currently ignored, e.g. because an automatic mode is on
+    pub inactive: bool,
+    /// Entries of a menu control, at most [`MAX_MENU_ITEMS`]
+    pub menu: Vec<MenuItem>,
+}
+
+impl Control {
+    /// Converts a control description of the C library, without menu entries
+    pub(crate) fn from_info(info: &ffi::CapControlInfo) -> Self {
+        let name = unsafe { CStr::from_ptr(info.name.as_ptr() as *const c_char) };
+        let kind = match info.type_ {
+            ffi::CAPCTRLTYPE_BOOLEAN => Kind::Boolean,
+            ffi::CAPCTRLTYPE_MENU => Kind::Menu,
+            ffi::CAPCTRLTYPE_BUTTON => Kind::Button,
+            _ => Kind::Integer,
+        };
+
+        Control {
+            id: info.id,
+            name: name.to_string_lossy().into_owned(),
+            kind,
+            min: info.min,
+            max: info.max,
+            step: info.step,
+            default: info.defaultValue,
+            read_only: info.flags & ffi::CAPCTRLFLAG_READONLY != 0,
+            inactive: info.flags & ffi::CAPCTRLFLAG_INACTIVE != 0,
+            menu: Vec::new(),
+        }
+    }
+
+    /// Returns true if the control is called `name`
+    ///
+    /// Case, spaces and punctuation are ignored, so `exposure_time_absolute` matches
+    /// `Exposure Time, Absolute`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::control::{Control, Kind};
+    ///
+    /// let control = Control {
+    ///     id: 0x009a0902,
+    ///     name: "Exposure Time, Absolute".to_string(),
+    ///     kind: Kind::Integer,
+    ///     min: 3,
+    ///     max: 2047,
+    ///     step: 1,
+    ///     default: 250,
+    ///     read_only: false,
+    ///     inactive: false,
+    ///     menu: Vec::new(),
+    /// };
+    /// assert!(control.is_named("exposure_time_absolute"));
+    /// assert!(!control.is_named("exposure"));
+    /// ```
+    pub fn is_named(&self, name: &str) -> bool {
+        let normalize = |s: &str| -> String {
+            s.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect()
+        };
+        normalize(&self.name) == normalize(name)
+    }
+
+    /// Returns the value of the menu entry called `name`
+    pub fn menu_value(&self, name: &str) -> Option<i32> {
+        self.menu
+            .iter()
+            .find(|item| item.name.eq_ignore_ascii_case(name))
+            .map(|item| item.value)
+    }
+}
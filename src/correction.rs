@@ -0,0 +1,297 @@
+use std::fmt;
+use std::io;
+
+#[cfg(feature = "profile")]
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::average::{self, Mode};
+#[cfg(feature = "profile")]
+use crate::format::Format;
+use crate::frame::Frame;
+#[cfg(feature = "profile")]
+use crate::profile;
+use crate::roi::Roi;
+
+/// Fixed point scale of the per-pixel gain
+const ONE: u32 = 1024;
+
+/// Largest gain applied to a pixel, in units of [`ONE`]
+const MAX_GAIN: u32 = u16::MAX as u32;
+
+/// Magic bytes of a stored correction
+#[cfg(feature = "profile")]
+const MAGIC: &[u8; 4] = b"OPFF";
+
+#[derive(Clone, PartialEq)]
+/// Flat-field and dark-frame correction
+///
+/// Every pixel is corrected as `(raw - dark) * gain`, where the gain is chosen so that the
+/// flat-field target comes out uniform at its average brightness. Each color channel is
+/// corrected on its own, so a tinted illumination keeps its average color.
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::correction::Correction;
+/// use openpnp_capture::Frame;
+/// use std::time::Instant;
+///
+/// // Synthetic ring light vignetting: the brightness falls off towards the border
+/// let (width, height) = (32u32, 24u32);
+/// let vignetting = |x: u32, y: u32| {
+///     let dx = x as f64 - 15.5;
+///     let dy = y as f64 - 11.5;
+///     1.0 - 0.5 * (dx * dx + dy * dy) / (15.5 * 15.5 + 11.5 * 11.5)
+/// };
+/// let frame = |scene: &dyn Fn(u32, u32) -> f64| {
+///     let mut data = Vec::new();
+///     for y in 0..height {
+///         for x in 0..width {
+///             let value = (8.0 + scene(x, y) * vignetting(x, y)).round() as u8;
+///             data.extend_from_slice(&[value; 3]);
+///         }
+///     }
+///     Frame { width, height, sequence: 0, timestamp: Instant::now(), data }
+/// };
+///
+/// let flat = frame(&|_, _| 200.0);
+/// let dark = frame(&|_, _| 0.0);
+/// let correction = Correction::new(&flat, Some(&dark)).unwrap();
+///
+/// // A uniform gray part looks uniform again after correction
+/// let mut part = frame(&|_, _| 100.0);
+/// let spread = |f: &Frame| f.data.iter().max().unwrap() - f.data.iter().min().unwrap();
+/// assert!(spread(&part) > 40);
+/// correction.apply(&mut part).unwrap();
+/// assert!(spread(&part) <= 2);
+/// ```
+pub struct Correction {
+    width: u32,
+    height: u32,
+    /// Dark frame, three bytes per pixel
+    dark: Vec<u8>,
+    /// Per-pixel gain in units of [`ONE`], three values per pixel
+    gain: Vec<u16>,
+}
+
+impl fmt::Debug for Correction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Correction")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .finish()
+    }
+}
+
+impl Correction {
+    /// Computes the correction from a flat-field and an optional dark frame
+    ///
+    /// The flat-field is a frame of a uniform target under the working illumination, the dark
+    /// frame is captured with the lights off and the lens covered. Both should be averaged over
+    /// several frames to suppress noise, see [`Correction::from_frames`].
+    pub fn new(flat: &Frame, dark: Option<&Frame>) -> io::Result<Self> {
+        match dark {
+            Some(dark) => average::check([flat, dark])?,
+            None => average::check([flat])?,
+        }
+
+        let dark = match dark {
+            Some(dark) => dark.data.clone(),
+            None => vec![0; flat.data.len()],
+        };
+
+        // Average brightness of the flat-field per channel
+        let mut mean = [0.0f64; 3];
+        for (px, dk) in flat.data.chunks_exact(3).zip(dark.chunks_exact(3)) {
+            for c in 0..3 {
+                mean[c] += px[c].saturating_sub(dk[c]) as f64;
+            }
+        }
+        let pixels = (flat.data.len() / 3).max(1) as f64;
+        let mean = mean.map(|sum| sum / pixels);
+
+        let gain = flat
+            .data
+            .iter()
+            .zip(&dark)
+            .enumerate()
+            .map(|(i, (&value, &dark))| {
+                let signal = value.saturating_sub(dark);
+                if signal == 0 {
+                    // Dead pixel, nothing to scale
+                    return ONE as u16;
+                }
+                let gain = (mean[i % 3] / signal as f64 * ONE as f64).round();
+                gain.min(MAX_GAIN as f64) as u16
+            })
+            .collect();
+
+        Ok(Correction {
+            width: flat.width,
+            height: flat.height,
+            dark,
+            gain,
+        })
+    }
+
+    /// Computes the correction from averages of flat-field and dark frames
+    ///
+    /// `dark` may be empty if no dark frames were taken. See [`Correction::new`].
+    pub fn from_frames(flat: &[Frame], dark: &[Frame]) -> io::Result<Self> {
+        let flat = average::stack(flat, Mode::Mean)?;
+        if dark.is_empty() {
+            return Correction::new(&flat, None);
+        }
+        let dark = average::stack(dark, Mode::Mean)?;
+        Correction::new(&flat, Some(&dark))
+    }
+
+    /// Returns the frame size the correction was computed for
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Corrects a region of an RGB24 frame in place
+    ///
+    /// `roi` must be clipped to the correction size and `data` holds just the region.
+    pub(crate) fn apply_region(&self, data: &mut [u8], roi: &Roi) {
+        let (width, row) = (self.width as usize * 3, roi.width as usize * 3);
+        for (y, line) in data.chunks_exact_mut(row).enumerate() {
+            let start = (roi.y as usize + y) * width + roi.x as usize * 3;
+            let dark = &self.dark[start..start + row];
+            let gain = &self.gain[start..start + row];
+            for ((value, &dark), &gain) in line.iter_mut().zip(dark).zip(gain) {
+                let signal = value.saturating_sub(dark) as u32;
+                *value = ((signal * gain as u32 + ONE / 2) / ONE).min(255) as u8;
+            }
+        }
+    }
+
+    /// Corrects an RGB24 frame in place
+    pub fn apply(&self, frame: &mut Frame) -> io::Result<()> {
+        if (frame.width, frame.height) != self.size() || frame.data.len() != self.dark.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame size does not match correction",
+            ));
+        }
+        self.apply_region(&mut frame.data, &Roi::full(self.width, self.height));
+        Ok(())
+    }
+
+    /// Returns the file a correction is stored in for a device unique ID and format
+    #[cfg(feature = "profile")]
+    fn path(dir: &Path, unique_id: &str, format: &Format) -> PathBuf {
+        dir.join(format!(
+            "{}-{}x{}-{}.flat",
+            profile::file_name(unique_id),
+            format.width,
+            format.height,
+            profile::file_name(&format.fourcc.to_string())
+        ))
+    }
+
+    /// Loads the correction of a device and format from a directory
+    ///
+    /// `unique_id` is the [`crate::Device::id`] of the camera and `format` is usually the
+    /// [`crate::Stream::format`] in use. Unlike profiles and calibrations, corrections are
+    /// stored in a compact binary file, the per-pixel tables are too large for TOML.
+    #[cfg(feature = "profile")]
+    pub fn load<P: AsRef<Path>>(dir: P, unique_id: &str, format: &Format) -> io::Result<Self> {
+        let bytes = fs::read(Correction::path(dir.as_ref(), unique_id, format))?;
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid correction file");
+
+        if bytes.len() < 12 || &bytes[..4] != MAGIC {
+            return Err(invalid());
+        }
+        let word =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let (width, height) = (word(4), word(8));
+        if (width, height) != (format.width, format.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "correction size does not match format",
+            ));
+        }
+        let len = width as usize * height as usize * 3;
+        let body = &bytes[12..];
+        if body.len() != len * 3 {
+            return Err(invalid());
+        }
+
+        Ok(Correction {
+            width,
+            height,
+            dark: body[..len].to_vec(),
+            gain: body[len..]
+                .chunks_exact(2)
+                .map(|b| u16::from_le_bytes([b[0], b[1]]))
+                .collect(),
+        })
+    }
+
+    /// Saves the correction of a device and format into a directory, creating it if needed
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "profile")]
+    /// # {
+    /// use openpnp_capture::correction::Correction;
+    /// use openpnp_capture::{Format, Frame};
+    /// use std::time::Instant;
+    ///
+    /// let flat = |value| Frame {
+    ///     width: 4,
+    ///     height: 2,
+    ///     sequence: 0,
+    ///     timestamp: Instant::now(),
+    ///     data: vec![value; 4 * 2 * 3],
+    /// };
+    /// let format = Format::default().width(4).height(2);
+    /// let dir = std::env::temp_dir().join(format!("opc-correction-{}", std::process::id()));
+    ///
+    /// // IDs differing only in punctuation are stored separately
+    /// let a = Correction::new(&flat(100), None).unwrap();
+    /// let b = Correction::new(&flat(200), Some(&flat(50))).unwrap();
+    /// a.save(&dir, "usb:1.2", &format).unwrap();
+    /// b.save(&dir, "usb_1_2", &format).unwrap();
+    /// assert!(Correction::load(&dir, "usb:1.2", &format).unwrap() == a);
+    /// assert!(Correction::load(&dir, "usb_1_2", &format).unwrap() == b);
+    ///
+    /// // A correction never applies to another frame size
+    /// let large = Format::default().width(8).height(4);
+    /// assert!(a.save(&dir, "usb:1.2", &large).is_err());
+    /// for entry in std::fs::read_dir(&dir).unwrap() {
+    ///     let path = entry.unwrap().path();
+    ///     let name = path.file_name().unwrap().to_str().unwrap().replace("4x2", "8x4");
+    ///     std::fs::copy(&path, dir.join(name)).unwrap();
+    /// }
+    /// assert!(Correction::load(&dir, "usb:1.2", &large).is_err());
+    /// std::fs::remove_dir_all(&dir).unwrap();
+    /// # }
+    /// ```
+    #[cfg(feature = "profile")]
+    pub fn save<P: AsRef<Path>>(&self, dir: P, unique_id: &str, format: &Format) -> io::Result<()> {
+        if self.size() != (format.width, format.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "correction size does not match format",
+            ));
+        }
+        let mut bytes = Vec::with_capacity(12 + self.dark.len() * 3);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&self.width.to_le_bytes());
+        bytes.extend_from_slice(&self.height.to_le_bytes());
+        bytes.extend_from_slice(&self.dark);
+        for gain in &self.gain {
+            bytes.extend_from_slice(&gain.to_le_bytes());
+        }
+
+        fs::create_dir_all(dir.as_ref())?;
+        fs::write(Correction::path(dir.as_ref(), unique_id, format), bytes)
+    }
+}
@@ -1,9 +1,17 @@
 use openpnp_capture_sys as ffi;
+use std::ffi::CStr;
+use std::io;
+use std::os::raw::c_char;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 use crate::context::CONTEXT;
 use crate::format;
+use crate::property;
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// Capture device
 pub struct Device {
     /// Index
@@ -14,6 +22,113 @@ pub struct Device {
     pub id: String,
 }
 
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Extended device descriptor
+///
+/// Fields the platform does not know about are empty or `None`.
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// use openpnp_capture::DeviceInfo;
+///
+/// let info = DeviceInfo {
+///     path: "/dev/video0".to_string(),
+///     bus: "usb-0000:00:14.0-2".to_string(),
+///     driver: "uvcvideo".to_string(),
+///     capabilities: 0x0420_0001,
+///     vendor_id: Some(0x046d),
+///     product_id: Some(0x0825),
+///     serial: Some("A1B2C3".to_string()),
+/// };
+/// let json = serde_json::to_string(&info).unwrap();
+/// assert_eq!(serde_json::from_str::<DeviceInfo>(&json).unwrap(), info);
+/// let toml = toml::to_string(&info).unwrap();
+/// assert_eq!(toml::from_str::<DeviceInfo>(&toml).unwrap(), info);
+///
+/// // Unknown fields are left out
+/// let info = DeviceInfo::default();
+/// let toml = toml::to_string(&info).unwrap();
+/// assert!(!toml.contains("serial"));
+/// assert_eq!(toml::from_str::<DeviceInfo>(&toml).unwrap(), info);
+/// # }
+/// ```
+pub struct DeviceInfo {
+    /// Device node (Linux), device path (Windows) or unique ID (macOS)
+    pub path: String,
+    /// Bus location, e.g. `usb-0000:00:14.0-2`
+    pub bus: String,
+    /// Driver name
+    pub driver: String,
+    /// Platform specific capability flags (V4L2 device caps on Linux)
+    pub capabilities: u32,
+    /// USB vendor ID
+    pub vendor_id: Option<u16>,
+    /// USB product ID
+    pub product_id: Option<u16>,
+    /// USB serial number
+    pub serial: Option<String>,
+}
+
+impl DeviceInfo {
+    /// Fills in the USB IDs and serial number from sysfs
+    #[cfg(target_os = "linux")]
+    fn read_sysfs(&mut self) {
+        use std::fs;
+        use std::path::Path;
+
+        let node = match Path::new(&self.path).file_name() {
+            Some(node) => node,
+            None => return,
+        };
+        let dev = Path::new("/sys/class/video4linux")
+            .join(node)
+            .join("device");
+        let dev = match fs::canonicalize(dev) {
+            Ok(dev) => dev,
+            Err(_) => return,
+        };
+
+        // The device link points to the USB interface, the descriptor attributes live on the
+        // USB device above it.
+        let read = |dir: &Path, attr: &str| {
+            fs::read_to_string(dir.join(attr))
+                .ok()
+                .map(|val| val.trim().to_string())
+                .filter(|val| !val.is_empty())
+        };
+        if let Some(dir) = dev
+            .ancestors()
+            .take(3)
+            .find(|dir| dir.join("idVendor").exists())
+        {
+            let hex = |val: String| u16::from_str_radix(&val, 16).ok();
+            self.vendor_id = self
+                .vendor_id
+                .or_else(|| read(dir, "idVendor").and_then(hex));
+            self.product_id = self
+                .product_id
+                .or_else(|| read(dir, "idProduct").and_then(hex));
+            self.serial = self.serial.take().or_else(|| read(dir, "serial"));
+        }
+    }
+
+    /// Fills in the USB IDs from a path containing `vid_XXXX` and `pid_XXXX` (Windows)
+    fn parse_path(&mut self) {
+        let path = self.path.to_lowercase();
+        let find = |key: &str| {
+            let start = path.find(key)? + key.len();
+            path.get(start..start + 4)
+                .and_then(|val| u16::from_str_radix(val, 16).ok())
+        };
+        self.vendor_id = self.vendor_id.or_else(|| find("vid_"));
+        self.product_id = self.product_id.or_else(|| find("pid_"));
+    }
+}
+
 impl Device {
     /// Returns a list of valid device indices
     ///
@@ -36,6 +151,29 @@ impl Device {
         indices
     }
 
+    /// Enumerates the devices again, e.g. after a camera was plugged in or reconnected
+    ///
+    /// Device indices may change, so devices obtained before must be looked up again with
+    /// [`Device::refresh`]. [`crate::Stream::new`] does this on its own. Open streams are not
+    /// affected.
+    pub fn rescan() -> io::Result<()> {
+        let context = CONTEXT.lock().unwrap().inner;
+        property::result(unsafe { ffi::Cap_rescanDevices(context) })
+    }
+
+    /// Returns the device with the same unique ID at its current index
+    ///
+    /// Returns `None` if the device is gone. See [`Device::rescan`].
+    pub fn refresh(&self) -> Option<Self> {
+        let unchanged = Device::new(self.index).filter(|dev| dev.id == self.id);
+        unchanged.or_else(|| {
+            Device::enumerate()
+                .into_iter()
+                .filter_map(Device::new)
+                .find(|dev| dev.id == self.id)
+        })
+    }
+
     /// Returns a device instance
     ///
     /// # Example
@@ -61,6 +199,56 @@ impl Device {
         })
     }
 
+    /// Returns the extended device descriptor
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::device::Device;
+    /// let dev = Device::new(0);
+    /// if let Some(dev) = dev {
+    ///     println!("Info: {:?}", dev.info());
+    /// }
+    /// ```
+    pub fn info(&self) -> Option<DeviceInfo> {
+        let context = CONTEXT.lock().unwrap().inner;
+        let mut desc = ffi::CapDeviceDescriptor {
+            path: std::ptr::null(),
+            busInfo: std::ptr::null(),
+            driver: std::ptr::null(),
+            capabilities: 0,
+            vendorID: 0,
+            productID: 0,
+        };
+        let res = unsafe { ffi::Cap_getDeviceDescriptor(context, self.index, &mut desc) };
+        if res != ffi::CAPRESULT_OK {
+            return None;
+        }
+
+        let string = |ptr: *const c_char| {
+            if ptr.is_null() {
+                return String::new();
+            }
+            unsafe { CStr::from_ptr(ptr).to_string_lossy().into_owned() }
+        };
+        let id = |id: u16| if id > 0 { Some(id) } else { None };
+
+        let mut info = DeviceInfo {
+            path: string(desc.path),
+            bus: string(desc.busInfo),
+            driver: string(desc.driver),
+            capabilities: desc.capabilities,
+            vendor_id: id(desc.vendorID),
+            product_id: id(desc.productID),
+            serial: None,
+        };
+        #[cfg(target_os = "linux")]
+        info.read_sysfs();
+        info.parse_path();
+
+        Some(info)
+    }
+
     /// Returns the supported formats
     ///
     /// # Example
@@ -87,7 +275,18 @@ impl Device {
             };
             let res = unsafe { ffi::Cap_getFormatInfo(context, self.index, i, &mut format) };
             if let ffi::CAPRESULT_OK = res {
-                formats.push(format::Format::from(format));
+                let mut format = format::Format::from(format);
+
+                // The fps reported in the format info is rounded, fetch the exact rate
+                let (mut num, mut den) = (0, 0);
+                let res = unsafe {
+                    ffi::Cap_getFormatFrameInterval(context, self.index, i, &mut num, &mut den)
+                };
+                if res == ffi::CAPRESULT_OK && num > 0 && den > 0 {
+                    format.fps = format::FrameRate::from_interval(num, den);
+                }
+
+                formats.push(format);
             }
         }
 
@@ -0,0 +1,257 @@
+use std::io;
+use std::time::{Duration, Instant};
+
+use crate::frame::Frame;
+use crate::property::Property;
+use crate::roi::Roi;
+use crate::stream::Stream;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// How the brightness of a frame is measured
+pub enum Measure {
+    /// Mean luminance
+    Mean,
+    /// Luminance below which the given percentage of pixels fall
+    ///
+    /// High percentiles keep specular highlights from shiny parts out of saturation.
+    Percentile(u8),
+}
+
+/// Returns the brightness of a region of a frame in the range `0..=255`
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::exposure::{brightness, Measure};
+/// use openpnp_capture::{Frame, Roi};
+/// use std::time::Instant;
+///
+/// let frame = Frame {
+///     width: 4,
+///     height: 1,
+///     sequence: 0,
+///     timestamp: Instant::now(),
+///     data: [10, 20, 30, 240].iter().flat_map(|v| vec![*v; 3]).collect(),
+/// };
+/// let roi = Roi::full(4, 1);
+/// assert_eq!(brightness(&frame, &roi, Measure::Mean), 75.0);
+/// assert_eq!(brightness(&frame, &roi, Measure::Percentile(50)), 20.0);
+/// assert_eq!(brightness(&frame, &roi, Measure::Percentile(100)), 240.0);
+/// ```
+pub fn brightness(frame: &Frame, roi: &Roi, measure: Measure) -> f64 {
+    let luma = frame.luma(roi);
+    if luma.is_empty() {
+        return 0.0;
+    }
+
+    match measure {
+        Measure::Mean => luma.iter().map(|v| *v as u64).sum::<u64>() as f64 / luma.len() as f64,
+        Measure::Percentile(percent) => {
+            let mut histogram = [0usize; 256];
+            for v in &luma {
+                histogram[*v as usize] += 1;
+            }
+
+            let rank = (luma.len() * percent.min(100) as usize).div_ceil(100);
+            let mut seen = 0;
+            for (value, count) in histogram.iter().enumerate() {
+                seen += count;
+                if seen >= rank.max(1) {
+                    return value as f64;
+                }
+            }
+            255.0
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Outcome of an auto exposure run
+pub struct Outcome {
+    /// Exposure value the stream is left at
+    pub exposure: i32,
+    /// Gain value the stream is left at, if gain was adjusted
+    pub gain: Option<i32>,
+    /// Brightness of the last frame
+    pub brightness: f64,
+    /// Number of frames measured
+    pub iterations: u32,
+    /// Whether the target brightness was reached
+    pub converged: bool,
+}
+
+#[derive(Debug, Copy, Clone)]
+/// Software auto exposure controller
+///
+/// Hardware auto exposure is disabled and the exposure property is searched for the value that
+/// brings the measured brightness closest to the target. Since the unit of the exposure property
+/// is platform dependent (linear on Linux, logarithmic on Windows), a bisection is used which only
+/// assumes that brightness grows with the property value. If exposure alone cannot reach the
+/// target and gain control is enabled, gain is searched next.
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::exposure::{AutoExposure, Measure};
+/// use openpnp_capture::{Device, Format, Stream};
+///
+/// let dev = Device::new(0);
+/// if let Some(dev) = &dev {
+///     if let Some(mut stream) = Stream::new(&dev, &Format::default()) {
+///         let outcome = AutoExposure::new(110)
+///             .measure(Measure::Percentile(95))
+///             .gain(true)
+///             .run(&mut stream);
+///         println!("Outcome: {:?}", outcome);
+///     }
+/// }
+/// ```
+pub struct AutoExposure {
+    /// Target brightness
+    pub target: u8,
+    /// Accepted deviation from the target
+    pub tolerance: u8,
+    /// Brightness measure
+    pub measure: Measure,
+    /// Region to measure, the whole frame if unset
+    pub roi: Option<Roi>,
+    /// Whether gain may be adjusted as well
+    pub gain: bool,
+    /// Maximum duration of a run
+    pub timeout: Duration,
+}
+
+impl AutoExposure {
+    /// Returns a controller aiming for the given brightness
+    pub fn new(target: u8) -> Self {
+        AutoExposure {
+            target,
+            tolerance: 8,
+            measure: Measure::Mean,
+            roi: None,
+            gain: false,
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Builder: sets the accepted deviation from the target
+    pub fn tolerance(mut self, tolerance: u8) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    /// Builder: sets the brightness measure
+    pub fn measure(mut self, measure: Measure) -> Self {
+        self.measure = measure;
+        self
+    }
+
+    /// Builder: sets the region to measure
+    pub fn roi(mut self, roi: Roi) -> Self {
+        self.roi = Some(roi);
+        self
+    }
+
+    /// Builder: allows adjusting gain when exposure alone is not enough
+    pub fn gain(mut self, gain: bool) -> Self {
+        self.gain = gain;
+        self
+    }
+
+    /// Builder: sets the maximum duration of a run
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Adjusts the exposure (and optionally gain) of a stream until the target is reached
+    ///
+    /// Returns an error of kind `TimedOut` if the timeout expires first.
+    pub fn run(&self, stream: &mut Stream) -> io::Result<Outcome> {
+        let deadline = Instant::now() + self.timeout;
+
+        stream.set_auto_property(Property::Exposure, false)?;
+        let mut outcome = Outcome {
+            exposure: stream.property(Property::Exposure)?,
+            gain: None,
+            brightness: 0.0,
+            iterations: 0,
+            converged: false,
+        };
+
+        outcome.exposure = self.search(stream, Property::Exposure, deadline, &mut outcome)?;
+        if outcome.converged || !self.gain {
+            return Ok(outcome);
+        }
+
+        // Gain is optional, not every camera supports controlling it
+        if stream.property_limits(Property::Gain).is_err() {
+            return Ok(outcome);
+        }
+        let _ = stream.set_auto_property(Property::Gain, false);
+        outcome.gain = Some(self.search(stream, Property::Gain, deadline, &mut outcome)?);
+        Ok(outcome)
+    }
+
+    /// Bisects a property for the target brightness and returns the best value found
+    fn search(
+        &self,
+        stream: &mut Stream,
+        prop: Property,
+        deadline: Instant,
+        outcome: &mut Outcome,
+    ) -> io::Result<i32> {
+        let limits = stream.property_limits(prop)?;
+        let (mut lo, mut hi) = (limits.min, limits.max);
+        let mut value = limits.clamp(stream.property(prop)?);
+        let mut best = (value, f64::MAX);
+        let mut collapsed = false;
+
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining == Duration::from_secs(0) {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "auto exposure did not converge in time",
+                ));
+            }
+
+            stream.set_property(prop, value)?;
+            let frame = stream.capture_after(Instant::now(), 0, remaining)?;
+            let roi = self
+                .roi
+                .unwrap_or_else(|| Roi::full(frame.width, frame.height));
+            outcome.brightness = brightness(&frame, &roi, self.measure);
+            outcome.iterations += 1;
+
+            let error = outcome.brightness - self.target as f64;
+            if error.abs() < best.1 {
+                best = (value, error.abs());
+            }
+            if error.abs() <= self.tolerance as f64 {
+                outcome.converged = true;
+                return Ok(value);
+            }
+
+            if error < 0.0 {
+                lo = value;
+            } else {
+                hi = value;
+            }
+            if hi as i64 - lo as i64 <= 1 {
+                // Measure the other end of the collapsed interval once before giving up
+                let other = if value == lo { hi } else { lo };
+                if collapsed || other == value {
+                    break;
+                }
+                collapsed = true;
+                value = other;
+                continue;
+            }
+            value = lo + ((hi as i64 - lo as i64) / 2) as i32;
+        }
+
+        stream.set_property(prop, best.0)?;
+        Ok(best.0)
+    }
+}
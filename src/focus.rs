@@ -0,0 +1,186 @@
+use std::io;
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+use crate::frame::Frame;
+use crate::property::Property;
+use crate::roi::Roi;
+use crate::stream::Stream;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// Sharpness metric, higher scores mean sharper images
+pub enum Metric {
+    /// Variance of the Laplacian
+    LaplacianVariance,
+    /// Mean squared Sobel gradient magnitude
+    Tenengrad,
+    /// Mean squared difference between pixels two columns apart
+    Brenner,
+}
+
+/// Returns the sharpness of a region of a frame
+///
+/// Regions smaller than 3x3 pixels score zero.
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::focus::{score, Metric};
+/// use openpnp_capture::{Frame, Roi};
+/// use std::time::Instant;
+///
+/// // A hard vertical edge is sharper than a soft one
+/// let frame = |row: &[u8]| Frame {
+///     width: row.len() as u32,
+///     height: 4,
+///     sequence: 0,
+///     timestamp: Instant::now(),
+///     data: row.repeat(4).iter().flat_map(|v| vec![*v; 3]).collect(),
+/// };
+/// let hard = frame(&[0, 0, 0, 255, 255, 255]);
+/// let soft = frame(&[0, 50, 100, 150, 200, 255]);
+///
+/// let roi = Roi::full(6, 4);
+/// for metric in &[Metric::LaplacianVariance, Metric::Tenengrad, Metric::Brenner] {
+///     assert!(score(&hard, &roi, *metric) > score(&soft, &roi, *metric));
+/// }
+/// ```
+pub fn score(frame: &Frame, roi: &Roi, metric: Metric) -> f64 {
+    let roi = roi.clip(frame.width, frame.height);
+    if roi.width < 3 || roi.height < 3 {
+        return 0.0;
+    }
+
+    let luma = frame.luma(&roi);
+    let (w, h) = (roi.width as usize, roi.height as usize);
+    let px = |x: usize, y: usize| luma[y * w + x] as f64;
+
+    match metric {
+        Metric::LaplacianVariance => {
+            let mut sum = 0.0;
+            let mut sum_sq = 0.0;
+            for y in 1..h - 1 {
+                for x in 1..w - 1 {
+                    let lap =
+                        4.0 * px(x, y) - px(x - 1, y) - px(x + 1, y) - px(x, y - 1) - px(x, y + 1);
+                    sum += lap;
+                    sum_sq += lap * lap;
+                }
+            }
+            let n = ((w - 2) * (h - 2)) as f64;
+            let mean = sum / n;
+            sum_sq / n - mean * mean
+        }
+        Metric::Tenengrad => {
+            let mut sum = 0.0;
+            for y in 1..h - 1 {
+                for x in 1..w - 1 {
+                    let gx = px(x + 1, y - 1) + 2.0 * px(x + 1, y) + px(x + 1, y + 1)
+                        - px(x - 1, y - 1)
+                        - 2.0 * px(x - 1, y)
+                        - px(x - 1, y + 1);
+                    let gy = px(x - 1, y + 1) + 2.0 * px(x, y + 1) + px(x + 1, y + 1)
+                        - px(x - 1, y - 1)
+                        - 2.0 * px(x, y - 1)
+                        - px(x + 1, y - 1);
+                    sum += gx * gx + gy * gy;
+                }
+            }
+            sum / ((w - 2) * (h - 2)) as f64
+        }
+        Metric::Brenner => {
+            let mut sum = 0.0;
+            for y in 0..h {
+                for x in 0..w - 2 {
+                    let diff = px(x + 2, y) - px(x, y);
+                    sum += diff * diff;
+                }
+            }
+            sum / ((w - 2) * h) as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Result of a focus sweep
+pub struct Sweep {
+    /// Focus value with the highest score, this is what the stream is left at
+    pub best: i32,
+    /// Focus values and their scores in the order they were measured
+    pub curve: Vec<(i32, f64)>,
+}
+
+/// Steps the focus through a range and sets the sharpest position
+///
+/// Automatic focus is disabled first. The range is clamped to the limits reported by the
+/// device. For every step, the first frame exposed after the lens was moved (plus one more to
+/// let the lens settle) is scored.
+///
+/// # Arguments
+///
+/// * `stream` - Stream to focus
+/// * `range` - Focus values to try
+/// * `step` - Distance between two focus values
+/// * `roi` - Region to score
+/// * `metric` - Sharpness metric
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::focus::{focus_sweep, Metric};
+/// use openpnp_capture::{Device, Format, Roi, Stream};
+///
+/// let dev = Device::new(0);
+/// if let Some(dev) = &dev {
+///     if let Some(mut stream) = Stream::new(&dev, &Format::default()) {
+///         let format = stream.format();
+///         let roi = Roi::centered(200, 200, format.width, format.height);
+///         let sweep = focus_sweep(&mut stream, 0..=250, 5, &roi, Metric::LaplacianVariance);
+///         println!("Sweep: {:?}", sweep);
+///     }
+/// }
+/// ```
+pub fn focus_sweep(
+    stream: &mut Stream,
+    range: RangeInclusive<i32>,
+    step: i32,
+    roi: &Roi,
+    metric: Metric,
+) -> io::Result<Sweep> {
+    if step <= 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "step must be positive",
+        ));
+    }
+
+    let limits = stream.property_limits(Property::Focus)?;
+    let start = limits.clamp(*range.start());
+    let end = limits.clamp(*range.end());
+
+    // Some drivers do not expose an auto focus mode, which is fine
+    let _ = stream.set_auto_property(Property::Focus, false);
+
+    let mut curve = Vec::new();
+    let mut value = start;
+    while value <= end {
+        stream.set_property(Property::Focus, value)?;
+        let frame = stream.capture_after(Instant::now(), 1, Duration::from_secs(2))?;
+        curve.push((value, score(&frame, roi, metric)));
+        value = match value.checked_add(step) {
+            Some(value) => value,
+            None => break,
+        };
+    }
+
+    // A NaN score would compare greater than any other
+    let best = curve
+        .iter()
+        .filter(|(_, score)| !score.is_nan())
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(value, _)| *value)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty focus range"))?;
+
+    stream.set_property(Property::Focus, best)?;
+    Ok(Sweep { best, curve })
+}
@@ -1,7 +1,52 @@
 use openpnp_capture_sys as ffi;
+use std::{fmt, io, str};
 
-#[derive(Debug, Default, Copy, Clone)]
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// Capture format
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// use openpnp_capture::format::{Format, FourCC, FrameRate};
+///
+/// let format = Format::default()
+///     .width(1280)
+///     .height(720)
+///     .fourcc(FourCC::new(b"MJPG"))
+///     .fps(FrameRate::new(15, 2));
+/// let toml = toml::to_string(&format).unwrap();
+/// assert!(toml.contains(r#"fourcc = "MJPG""#));
+/// assert!(toml.contains(r#"fps = "15/2""#));
+///
+/// let json = serde_json::to_string(&format).unwrap();
+/// let format: Format = serde_json::from_str(&json).unwrap();
+/// assert_eq!(format.fourcc, FourCC::new(b"MJPG"));
+/// assert_eq!(format.fps, FrameRate::new(15, 2));
+///
+/// let format: Format = toml::from_str(r#"
+///     width = 640
+///     height = 480
+///     fourcc = "YUYV"
+///     fps = 30
+/// "#).unwrap();
+/// assert_eq!(format.fps, FrameRate::from(30));
+/// assert_eq!(format.bpp, 0);
+///
+/// // An unset pixelformat round-trips as an empty string
+/// let format = Format::default().width(640).height(480);
+/// let toml = toml::to_string(&format).unwrap();
+/// assert!(toml.contains(r#"fourcc = """#));
+/// assert_eq!(toml::from_str::<Format>(&toml).unwrap(), format);
+/// # }
+/// ```
 pub struct Format {
     /// Width in pixels
     pub width: u32,
@@ -10,8 +55,9 @@ pub struct Format {
     /// Pixelformat
     pub fourcc: FourCC,
     /// Frames per second
-    pub fps: u32,
+    pub fps: FrameRate,
     /// Bits per pixel
+    #[cfg_attr(feature = "serde", serde(default))]
     pub bpp: u32,
 }
 
@@ -35,8 +81,16 @@ impl Format {
     }
 
     /// Builder: sets the frames per second
-    pub fn fps(mut self, fps: u32) -> Format {
-        self.fps = fps;
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::format::{Format, FrameRate};
+    /// let format = Format::default().fps(30);
+    /// let format = Format::default().fps(FrameRate::new(15, 2));
+    /// ```
+    pub fn fps<F: Into<FrameRate>>(mut self, fps: F) -> Format {
+        self.fps = fps.into();
         self
     }
 }
@@ -47,7 +101,7 @@ impl From<ffi::CapFormatInfo> for Format {
             width: val.width,
             height: val.height,
             fourcc: FourCC::from(val.fourcc),
-            fps: val.fps,
+            fps: FrameRate::from(val.fps),
             bpp: val.bpp,
         }
     }
@@ -106,3 +160,222 @@ impl From<u32> for FourCC {
         FourCC { repr }
     }
 }
+
+impl str::FromStr for FourCC {
+    type Err = io::Error;
+
+    /// Parses a four character code such as `MJPG`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = s.as_bytes();
+        if bytes.len() != 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid fourcc: {}", s),
+            ));
+        }
+        Ok(FourCC::new(&[bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+/// Frame rate as a fraction of frames per second, e.g. 15/2 for 7.5 fps
+pub struct FrameRate {
+    /// Numerator
+    pub numerator: u32,
+    /// Denominator
+    pub denominator: u32,
+}
+
+impl FrameRate {
+    /// Returns a frame rate of `numerator / denominator` frames per second
+    ///
+    /// The fraction is reduced, so equal rates compare equal.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::format::FrameRate;
+    /// let rate = FrameRate::new(30, 4);
+    /// assert_eq!(rate, FrameRate::new(15, 2));
+    /// assert_eq!(rate.as_f64(), 7.5);
+    /// ```
+    pub fn new(numerator: u32, denominator: u32) -> FrameRate {
+        let (mut a, mut b) = (numerator, denominator);
+        while b != 0 {
+            let t = a % b;
+            a = b;
+            b = t;
+        }
+        if a <= 1 {
+            return FrameRate {
+                numerator,
+                denominator,
+            };
+        }
+
+        FrameRate {
+            numerator: numerator / a,
+            denominator: denominator / a,
+        }
+    }
+
+    /// Returns the frame rate for a frame interval of `numerator / denominator` seconds
+    pub fn from_interval(numerator: u32, denominator: u32) -> FrameRate {
+        FrameRate::new(denominator, numerator)
+    }
+
+    /// Returns the frames per second as floating point number, zero if unset
+    pub fn as_f64(&self) -> f64 {
+        if self.denominator == 0 {
+            return 0.0;
+        }
+        self.numerator as f64 / self.denominator as f64
+    }
+
+    /// Returns true if no frame rate is set
+    pub fn is_zero(&self) -> bool {
+        self.numerator == 0 || self.denominator == 0
+    }
+}
+
+impl fmt::Display for FrameRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.denominator <= 1 || self.numerator % self.denominator == 0 {
+            return write!(f, "{}", self.as_f64() as u32);
+        }
+
+        // At most two decimals, e.g. 7.5 or 29.97
+        let string = format!("{:.2}", self.as_f64());
+        write!(f, "{}", string.trim_end_matches('0').trim_end_matches('.'))
+    }
+}
+
+impl From<u32> for FrameRate {
+    fn from(fps: u32) -> Self {
+        FrameRate {
+            numerator: fps,
+            denominator: 1,
+        }
+    }
+}
+
+impl str::FromStr for FrameRate {
+    type Err = io::Error;
+
+    /// Parses a frame rate such as `30`, `7.5` or `15/2`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::format::FrameRate;
+    /// assert_eq!("7.5".parse::<FrameRate>().unwrap(), FrameRate::new(15, 2));
+    /// assert_eq!("15/2".parse::<FrameRate>().unwrap(), FrameRate::new(15, 2));
+    /// assert_eq!("30".parse::<FrameRate>().unwrap().to_string(), "30");
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid frame rate: {}", s),
+            )
+        };
+
+        if let Some((num, den)) = s.split_once('/') {
+            let num = num.trim().parse::<u32>().map_err(|_| invalid())?;
+            let den = den.trim().parse::<u32>().map_err(|_| invalid())?;
+            return Ok(FrameRate::new(num, den));
+        }
+
+        let (int, frac) = s.trim().split_once('.').unwrap_or((s.trim(), ""));
+        if frac.len() > 3 || !frac.chars().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        let den = 10u32.pow(frac.len() as u32);
+        let int = int.parse::<u32>().map_err(|_| invalid())?;
+        let frac = if frac.is_empty() {
+            0
+        } else {
+            frac.parse::<u32>().map_err(|_| invalid())?
+        };
+        let num = int
+            .checked_mul(den)
+            .and_then(|num| num.checked_add(frac))
+            .ok_or_else(invalid)?;
+        Ok(FrameRate::new(num, den))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for FourCC {
+    /// Serializes the four character string, or an empty one if the code is unset
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.as_u32() == 0 {
+            return serializer.serialize_str("");
+        }
+        match str::from_utf8(&self.repr) {
+            Ok(string) => serializer.serialize_str(string),
+            Err(_) => Err(serde::ser::Error::custom("fourcc is not valid UTF-8")),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for FourCC {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = String::deserialize(deserializer)?;
+        if string.is_empty() {
+            return Ok(FourCC::default());
+        }
+        string.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for FrameRate {
+    /// Serializes whole rates as integer and fractional ones as `numerator/denominator` string
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.denominator == 1 {
+            serializer.serialize_u32(self.numerator)
+        } else {
+            serializer.serialize_str(&format!("{}/{}", self.numerator, self.denominator))
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for FrameRate {
+    /// Accepts integers, floats with up to three decimals and strings such as `7.5` or `15/2`
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = FrameRate;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a frame rate such as 30, 7.5 or \"15/2\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<FrameRate, E> {
+                u32::try_from(v)
+                    .map(FrameRate::from)
+                    .map_err(|_| E::custom("frame rate out of range"))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<FrameRate, E> {
+                u32::try_from(v)
+                    .map(FrameRate::from)
+                    .map_err(|_| E::custom("frame rate out of range"))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<FrameRate, E> {
+                self.visit_str(&format!("{:.3}", v))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<FrameRate, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
@@ -0,0 +1,53 @@
+use std::time::Instant;
+
+use crate::roi::Roi;
+
+#[derive(Debug, Clone)]
+/// Captured frame (always RGB24)
+pub struct Frame {
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+    /// Value of the stream frame counter belonging to the frame
+    pub sequence: u32,
+    /// Host time at which the frame was received from the device
+    pub timestamp: Instant,
+    /// Pixel data, three bytes per pixel
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    /// Returns the luminance of a region as 8-bit grayscale, row by row
+    ///
+    /// The region is clipped to the frame. Luminance is computed with the BT.601 weights.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::{Frame, Roi};
+    /// use std::time::Instant;
+    ///
+    /// let frame = Frame {
+    ///     width: 2,
+    ///     height: 1,
+    ///     sequence: 0,
+    ///     timestamp: Instant::now(),
+    ///     data: vec![255, 255, 255, 0, 0, 0],
+    /// };
+    /// assert_eq!(frame.luma(&Roi::full(2, 1)), vec![255, 0]);
+    /// ```
+    pub fn luma(&self, roi: &Roi) -> Vec<u8> {
+        let roi = roi.clip(self.width, self.height);
+        let mut luma = Vec::with_capacity((roi.width * roi.height) as usize);
+        for y in roi.y..roi.y + roi.height {
+            let start = ((y * self.width + roi.x) * 3) as usize;
+            let end = start + (roi.width * 3) as usize;
+            for px in self.data[start..end].chunks_exact(3) {
+                let luminance = 77 * px[0] as u32 + 150 * px[1] as u32 + 29 * px[2] as u32;
+                luma.push(((luminance + 128) >> 8) as u8);
+            }
+        }
+        luma
+    }
+}
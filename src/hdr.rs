@@ -0,0 +1,411 @@
+use std::io;
+
+use crate::average;
+use crate::bracket::BracketFrame;
+use crate::frame::Frame;
+
+/// Gamma of the assumed camera response
+///
+/// Most webcams deliver roughly sRGB encoded pixels, a plain power law is close enough for
+/// merging.
+const GAMMA: f32 = 2.2;
+
+#[derive(Debug, Clone)]
+/// Linear radiance image, three `f32` values per pixel in relative units
+pub struct Radiance {
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+    /// Pixel data, three values per pixel
+    pub data: Vec<f32>,
+}
+
+impl Radiance {
+    /// Compresses the radiance back into an 8-bit frame with the global Reinhard operator
+    ///
+    /// The image is scaled so that its log-average luminance maps to middle gray, then the
+    /// luminance `L` is compressed to `L / (1 + L)` keeping the color ratios.
+    pub fn tone_map(&self) -> Frame {
+        let luminance = |px: &[f32]| 0.299 * px[0] + 0.587 * px[1] + 0.114 * px[2];
+
+        let pixels = self.data.len() / 3;
+        let log_sum: f64 = self
+            .data
+            .chunks_exact(3)
+            .map(|px| (luminance(px).max(0.0) as f64 + 1e-6).ln())
+            .sum();
+        let log_average = (log_sum / pixels.max(1) as f64).exp() as f32;
+        let scale = 0.18 / log_average;
+
+        let mut data = Vec::with_capacity(self.data.len());
+        for px in self.data.chunks_exact(3) {
+            let l = luminance(px).max(0.0) * scale;
+            let ratio = if l > 0.0 { scale / (1.0 + l) } else { 0.0 };
+            for c in px {
+                data.push(encode(c * ratio));
+            }
+        }
+
+        Frame {
+            width: self.width,
+            height: self.height,
+            sequence: 0,
+            timestamp: std::time::Instant::now(),
+            data,
+        }
+    }
+}
+
+/// Converts an 8-bit value into linear light
+fn linear(value: u8) -> f32 {
+    (value as f32 / 255.0).powf(GAMMA)
+}
+
+/// Converts linear light into a gamma encoded 8-bit value
+fn encode(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0).powf(1.0 / GAMMA) * 255.0).round() as u8
+}
+
+/// Merges frames of known relative exposure into a linear radiance image
+///
+/// Every pixel is the weighted average of `linear(value) / exposure` over all frames, with a
+/// hat shaped weight that favors well exposed values and ignores clipped ones. Pixels that are
+/// clipped in every frame are taken from the frame closest to mid-gray.
+///
+/// The exposure values only need to be proportional to the exposure time. On Linux, the
+/// exposure property is in units of 100µs and can be used directly. Gain is not taken into
+/// account, keep it constant while bracketing.
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::hdr::merge;
+/// use openpnp_capture::Frame;
+/// use std::time::Instant;
+///
+/// // Two pixels, one dark and one 64 times brighter, captured at two exposures
+/// let capture = |exposure: f32| {
+///     let data = [0.002f32, 0.128]
+///         .iter()
+///         .map(|radiance| (radiance * exposure).min(1.0).powf(1.0 / 2.2) * 255.0)
+///         .flat_map(|value| vec![value.round() as u8; 3])
+///         .collect();
+///     Frame { width: 2, height: 1, sequence: 0, timestamp: Instant::now(), data }
+/// };
+/// let short = capture(1.0);
+/// let long = capture(16.0);
+///
+/// let radiance = merge(&[(&short, 1.0), (&long, 16.0)]).unwrap();
+/// let ratio = radiance.data[3] / radiance.data[0];
+/// assert!((ratio - 64.0).abs() < 8.0);
+///
+/// let frame = radiance.tone_map();
+/// assert!(frame.data[0] < frame.data[3]);
+/// ```
+pub fn merge(frames: &[(&Frame, f64)]) -> io::Result<Radiance> {
+    average::check(frames.iter().map(|(frame, _)| *frame))?;
+    if frames.iter().any(|(_, exposure)| *exposure <= 0.0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "exposure must be positive",
+        ));
+    }
+
+    let first = frames[0].0;
+    let mut data = Vec::with_capacity(first.data.len());
+    for i in 0..first.data.len() {
+        let mut sum = 0.0;
+        let mut weights = 0.0;
+        let mut fallback = (f32::MAX, 0.0);
+        for (frame, exposure) in frames {
+            let value = frame.data[i];
+            let radiance = linear(value) / *exposure as f32;
+            let weight = 1.0 - (2.0 * value as f32 / 255.0 - 1.0).abs();
+            sum += weight * radiance;
+            weights += weight;
+
+            let distance = (value as f32 - 127.5).abs();
+            if distance < fallback.0 {
+                fallback = (distance, radiance);
+            }
+        }
+        data.push(if weights > 0.0 {
+            sum / weights
+        } else {
+            fallback.1
+        });
+    }
+
+    Ok(Radiance {
+        width: first.width,
+        height: first.height,
+        data,
+    })
+}
+
+/// Merges a bracket captured with [`crate::Stream::capture_bracket`]
+///
+/// The exposure values read back from the driver are used as relative exposures, which is only
+/// valid for linear exposure units (see [`merge`]). Returns an error if the gain differs between
+/// the frames.
+pub fn merge_bracket(bracket: &[BracketFrame]) -> io::Result<Radiance> {
+    if let Some(first) = bracket.first() {
+        if bracket.iter().any(|b| b.applied.gain != first.applied.gain) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "gain differs between bracketed frames",
+            ));
+        }
+    }
+
+    let frames: Vec<(&Frame, f64)> = bracket
+        .iter()
+        .map(|b| (&b.frame, b.applied.exposure as f64))
+        .collect();
+    merge(&frames)
+}
+
+#[derive(Debug, Clone)]
+/// Single or multi channel `f32` image used for pyramid blending
+struct Plane {
+    width: usize,
+    height: usize,
+    channels: usize,
+    data: Vec<f32>,
+}
+
+impl Plane {
+    fn new(width: usize, height: usize, channels: usize) -> Self {
+        Plane {
+            width,
+            height,
+            channels,
+            data: vec![0.0; width * height * channels],
+        }
+    }
+
+    fn at(&self, x: isize, y: isize, c: usize) -> f32 {
+        let x = x.clamp(0, self.width as isize - 1) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.data[(y * self.width + x) * self.channels + c]
+    }
+
+    /// Blurs with the 5-tap binomial kernel, replicating the border
+    fn blur(&self) -> Plane {
+        const KERNEL: [f32; 5] = [1.0 / 16.0, 4.0 / 16.0, 6.0 / 16.0, 4.0 / 16.0, 1.0 / 16.0];
+
+        let mut horizontal = Plane::new(self.width, self.height, self.channels);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                for c in 0..self.channels {
+                    horizontal.data[(y * self.width + x) * self.channels + c] = KERNEL
+                        .iter()
+                        .enumerate()
+                        .map(|(k, w)| w * self.at(x as isize + k as isize - 2, y as isize, c))
+                        .sum();
+                }
+            }
+        }
+
+        let mut blurred = Plane::new(self.width, self.height, self.channels);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                for c in 0..self.channels {
+                    blurred.data[(y * self.width + x) * self.channels + c] = KERNEL
+                        .iter()
+                        .enumerate()
+                        .map(|(k, w)| w * horizontal.at(x as isize, y as isize + k as isize - 2, c))
+                        .sum();
+                }
+            }
+        }
+        blurred
+    }
+
+    /// Blurs and drops every other row and column
+    fn down(&self) -> Plane {
+        let blurred = self.blur();
+        let (width, height) = (self.width.div_ceil(2), self.height.div_ceil(2));
+        let mut down = Plane::new(width, height, self.channels);
+        for y in 0..height {
+            for x in 0..width {
+                for c in 0..self.channels {
+                    down.data[(y * width + x) * self.channels + c] =
+                        blurred.at(2 * x as isize, 2 * y as isize, c);
+                }
+            }
+        }
+        down
+    }
+
+    /// Scales up to the given size by pixel replication and blurs
+    fn up(&self, width: usize, height: usize) -> Plane {
+        let mut up = Plane::new(width, height, self.channels);
+        for y in 0..height {
+            for x in 0..width {
+                for c in 0..self.channels {
+                    up.data[(y * width + x) * self.channels + c] =
+                        self.at((x / 2) as isize, (y / 2) as isize, c);
+                }
+            }
+        }
+        up.blur()
+    }
+}
+
+/// Combines differently exposed frames into one well exposed frame (Mertens exposure fusion)
+///
+/// Every pixel is weighted by its local contrast, color saturation and closeness to mid-gray.
+/// The frames are blended with Laplacian pyramids to avoid seams. Unlike [`merge`], no exposure
+/// values are needed and the result is directly displayable.
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::hdr::fuse;
+/// use openpnp_capture::Frame;
+/// use std::time::Instant;
+///
+/// let frame = |data: Vec<u8>| Frame {
+///     width: 8,
+///     height: 8,
+///     sequence: 0,
+///     timestamp: Instant::now(),
+///     data,
+/// };
+/// // The left half is only visible in the long exposure, the right half in the short one
+/// let long: Vec<u8> = (0..64).flat_map(|i| vec![if i % 8 < 4 { 120 } else { 255 }; 3]).collect();
+/// let short: Vec<u8> = (0..64).flat_map(|i| vec![if i % 8 < 4 { 0 } else { 130 }; 3]).collect();
+///
+/// let fused = fuse(&[frame(short), frame(long)]).unwrap();
+/// assert!(fused.data[0] > 60 && fused.data[0] < 200);
+/// assert!(fused.data[7 * 3] > 60 && fused.data[7 * 3] < 200);
+///
+/// let empty = Frame { width: 0, height: 0, sequence: 0, timestamp: Instant::now(), data: vec![] };
+/// assert!(fuse(&[empty]).is_err());
+/// ```
+pub fn fuse(frames: &[Frame]) -> io::Result<Frame> {
+    average::check(frames)?;
+
+    let last = &frames[frames.len() - 1];
+    let (width, height) = (last.width as usize, last.height as usize);
+    // Planes replicate their border, so they must not be empty
+    if width == 0 || height == 0 || last.data.len() != width * height * 3 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "frames are empty or do not match their size",
+        ));
+    }
+    let levels = {
+        let mut levels = 1;
+        let mut size = width.min(height);
+        while size >= 16 && levels < 8 {
+            size = size.div_ceil(2);
+            levels += 1;
+        }
+        levels
+    };
+
+    // Per-pixel weights
+    let mut weights = Vec::with_capacity(frames.len());
+    for frame in frames {
+        let image = Plane {
+            width,
+            height,
+            channels: 3,
+            data: frame.data.iter().map(|v| *v as f32 / 255.0).collect(),
+        };
+        let gray =
+            |x: isize, y: isize| (image.at(x, y, 0) + image.at(x, y, 1) + image.at(x, y, 2)) / 3.0;
+
+        let mut weight = Plane::new(width, height, 1);
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let contrast = (4.0 * gray(x, y)
+                    - gray(x - 1, y)
+                    - gray(x + 1, y)
+                    - gray(x, y - 1)
+                    - gray(x, y + 1))
+                .abs();
+                let (r, g, b) = (image.at(x, y, 0), image.at(x, y, 1), image.at(x, y, 2));
+                let mean = (r + g + b) / 3.0;
+                let saturation =
+                    (((r - mean).powi(2) + (g - mean).powi(2) + (b - mean).powi(2)) / 3.0).sqrt();
+                let exposedness = [r, g, b]
+                    .iter()
+                    .map(|v| (-(v - 0.5).powi(2) / (2.0 * 0.2 * 0.2)).exp())
+                    .product::<f32>();
+                // Keep a small floor so flat gray regions still get a weight
+                weight.data[y as usize * width + x as usize] =
+                    (contrast + 1e-3) * (saturation + 1e-3) * exposedness + 1e-12;
+            }
+        }
+        weights.push((image, weight));
+    }
+
+    // Normalize the weights per pixel
+    for i in 0..width * height {
+        let sum: f32 = weights.iter().map(|(_, weight)| weight.data[i]).sum();
+        for (_, weight) in weights.iter_mut() {
+            weight.data[i] /= sum;
+        }
+    }
+
+    // Blend the Laplacian pyramids of the images with the Gaussian pyramids of the weights
+    let mut blended: Vec<Plane> = Vec::new();
+    for (image, weight) in weights {
+        let mut image = image;
+        let mut weight = weight;
+        for level in 0..levels {
+            let laplacian = if level + 1 < levels {
+                let down = image.down();
+                let mut laplacian = image.clone();
+                let up = down.up(image.width, image.height);
+                for (l, u) in laplacian.data.iter_mut().zip(&up.data) {
+                    *l -= u;
+                }
+                image = down;
+                laplacian
+            } else {
+                image.clone()
+            };
+
+            if blended.len() <= level {
+                blended.push(Plane::new(laplacian.width, laplacian.height, 3));
+            }
+            let target = &mut blended[level];
+            for (i, w) in weight.data.iter().enumerate() {
+                for c in 0..3 {
+                    target.data[i * 3 + c] += w * laplacian.data[i * 3 + c];
+                }
+            }
+
+            if level + 1 < levels {
+                weight = weight.down();
+            }
+        }
+    }
+
+    // Collapse the pyramid
+    let mut result = blended.pop().unwrap();
+    while let Some(mut level) = blended.pop() {
+        let up = result.up(level.width, level.height);
+        for (l, u) in level.data.iter_mut().zip(&up.data) {
+            *l += u;
+        }
+        result = level;
+    }
+
+    Ok(Frame {
+        width: last.width,
+        height: last.height,
+        sequence: last.sequence,
+        timestamp: last.timestamp,
+        data: result
+            .data
+            .iter()
+            .map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect(),
+    })
+}
@@ -0,0 +1,305 @@
+use openpnp_capture_sys as ffi;
+use std::ffi::CStr;
+use std::io;
+use std::os::raw::{c_int, c_ulong};
+use std::time::Instant;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::frame::Frame;
+
+/// Default JPEG quality
+pub const DEFAULT_QUALITY: u8 = 90;
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Chroma subsampling of a JPEG image
+pub enum Subsampling {
+    /// Full color resolution
+    Yuv444,
+    /// Half horizontal color resolution
+    Yuv422,
+    /// Half horizontal and vertical color resolution
+    #[default]
+    Yuv420,
+    /// Grayscale, no color at all
+    Gray,
+    /// Half vertical color resolution
+    Yuv440,
+    /// Quarter horizontal color resolution
+    Yuv411,
+}
+
+impl Subsampling {
+    fn id(&self) -> c_int {
+        (match self {
+            Subsampling::Yuv444 => ffi::TJSAMP_TJSAMP_444,
+            Subsampling::Yuv422 => ffi::TJSAMP_TJSAMP_422,
+            Subsampling::Yuv420 => ffi::TJSAMP_TJSAMP_420,
+            Subsampling::Gray => ffi::TJSAMP_TJSAMP_GRAY,
+            Subsampling::Yuv440 => ffi::TJSAMP_TJSAMP_440,
+            Subsampling::Yuv411 => ffi::TJSAMP_TJSAMP_411,
+        }) as c_int
+    }
+
+    fn from_id(id: c_int) -> Option<Self> {
+        [
+            Subsampling::Yuv444,
+            Subsampling::Yuv422,
+            Subsampling::Yuv420,
+            Subsampling::Gray,
+            Subsampling::Yuv440,
+            Subsampling::Yuv411,
+        ]
+        .iter()
+        .copied()
+        .find(|subsampling| subsampling.id() == id)
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// Size and subsampling of a JPEG image
+pub struct Header {
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+    /// Chroma subsampling, `None` for sampling factors without a [`Subsampling`] variant
+    pub subsampling: Option<Subsampling>,
+}
+
+/// Returns the last error of a turbojpeg instance
+fn error(handle: ffi::tjhandle) -> io::Error {
+    let msg = unsafe { CStr::from_ptr(ffi::tjGetErrorStr2(handle)) };
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        msg.to_string_lossy().into_owned(),
+    )
+}
+
+/// Converts an image dimension for turbojpeg
+fn dimension(value: u32) -> io::Result<c_int> {
+    match value {
+        1..=0xffff => Ok(value as c_int),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid JPEG dimension: {}", value),
+        )),
+    }
+}
+
+#[derive(Debug)]
+/// JPEG encoder for RGB24 frames
+///
+/// The encoder keeps its turbojpeg instance, so reuse it when encoding many frames.
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::jpeg::{self, Encoder, Subsampling};
+/// use openpnp_capture::Frame;
+/// use std::time::Instant;
+///
+/// // Horizontal gray ramp
+/// let (width, height) = (64u32, 48u32);
+/// let data = (0..width * height)
+///     .flat_map(|i| [(i % width * 4) as u8; 3])
+///     .collect();
+/// let frame = Frame { width, height, sequence: 0, timestamp: Instant::now(), data };
+///
+/// let mut encoder = Encoder::new().unwrap().quality(95).subsampling(Subsampling::Yuv444);
+/// let jpeg = encoder.encode(&frame).unwrap();
+/// assert!(jpeg.len() < frame.data.len());
+///
+/// let decoded = jpeg::decode(&jpeg).unwrap();
+/// assert_eq!((decoded.width, decoded.height), (width, height));
+/// let error = frame.data.iter().zip(&decoded.data).map(|(a, b)| (*a as i32 - *b as i32).abs());
+/// assert!(error.max().unwrap() <= 4);
+/// ```
+pub struct Encoder {
+    handle: ffi::tjhandle,
+    quality: u8,
+    subsampling: Subsampling,
+}
+
+// A turbojpeg instance may be moved between threads, it just must not be used concurrently
+unsafe impl Send for Encoder {}
+
+impl Encoder {
+    /// Returns an encoder with the default quality and subsampling
+    pub fn new() -> io::Result<Self> {
+        let handle = unsafe { ffi::tjInitCompress() };
+        if handle.is_null() {
+            return Err(io::Error::other("failed to initialize JPEG encoder"));
+        }
+        Ok(Encoder {
+            handle,
+            quality: DEFAULT_QUALITY,
+            subsampling: Subsampling::default(),
+        })
+    }
+
+    /// Builder: sets the quality, from 1 to 100
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.quality = quality.clamp(1, 100);
+        self
+    }
+
+    /// Builder: sets the chroma subsampling
+    pub fn subsampling(mut self, subsampling: Subsampling) -> Self {
+        self.subsampling = subsampling;
+        self
+    }
+
+    /// Encodes RGB24 pixel data, three bytes per pixel and row by row
+    pub fn encode_rgb(&mut self, data: &[u8], width: u32, height: u32) -> io::Result<Vec<u8>> {
+        let (w, h) = (dimension(width)?, dimension(height)?);
+        if data.len() < width as usize * height as usize * 3 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "buffer too small for image size",
+            ));
+        }
+
+        // Encode into a buffer of the worst case size so turbojpeg never reallocates it
+        let subsampling = self.subsampling.id();
+        let mut jpeg = vec![0u8; unsafe { ffi::tjBufSize(w, h, subsampling) } as usize];
+        let mut ptr = jpeg.as_mut_ptr();
+        let mut size = jpeg.len() as c_ulong;
+        let res = unsafe {
+            ffi::tjCompress2(
+                self.handle,
+                data.as_ptr(),
+                w,
+                0,
+                h,
+                ffi::TJPF_TJPF_RGB as c_int,
+                &mut ptr,
+                &mut size,
+                subsampling,
+                self.quality as c_int,
+                ffi::TJFLAG_NOREALLOC as c_int,
+            )
+        };
+        if res != 0 {
+            return Err(error(self.handle));
+        }
+        jpeg.truncate(size as usize);
+        Ok(jpeg)
+    }
+
+    /// Encodes a frame
+    pub fn encode(&mut self, frame: &Frame) -> io::Result<Vec<u8>> {
+        self.encode_rgb(&frame.data, frame.width, frame.height)
+    }
+}
+
+impl Drop for Encoder {
+    fn drop(&mut self) {
+        unsafe { ffi::tjDestroy(self.handle) };
+    }
+}
+
+#[derive(Debug)]
+/// JPEG decoder producing RGB24 frames
+///
+/// Decodes baseline and progressive JPEG as well as single MJPEG frames.
+pub struct Decoder {
+    handle: ffi::tjhandle,
+}
+
+// A turbojpeg instance may be moved between threads, it just must not be used concurrently
+unsafe impl Send for Decoder {}
+
+impl Decoder {
+    /// Returns a decoder
+    pub fn new() -> io::Result<Self> {
+        let handle = unsafe { ffi::tjInitDecompress() };
+        if handle.is_null() {
+            return Err(io::Error::other("failed to initialize JPEG decoder"));
+        }
+        Ok(Decoder { handle })
+    }
+
+    /// Reads the size and subsampling of a JPEG image without decoding it
+    pub fn header(&mut self, jpeg: &[u8]) -> io::Result<Header> {
+        let (mut width, mut height, mut subsampling, mut colorspace) = (0, 0, 0, 0);
+        let res = unsafe {
+            ffi::tjDecompressHeader3(
+                self.handle,
+                jpeg.as_ptr(),
+                jpeg.len() as c_ulong,
+                &mut width,
+                &mut height,
+                &mut subsampling,
+                &mut colorspace,
+            )
+        };
+        if res != 0 {
+            return Err(error(self.handle));
+        }
+
+        Ok(Header {
+            width: width as u32,
+            height: height as u32,
+            subsampling: Subsampling::from_id(subsampling),
+        })
+    }
+
+    /// Decodes a JPEG image into a buffer as RGB24 and returns its size
+    pub fn decode_into(&mut self, jpeg: &[u8], buf: &mut Vec<u8>) -> io::Result<(u32, u32)> {
+        let header = self.header(jpeg)?;
+        let len = header.width as usize * header.height as usize * 3;
+        if buf.len() != len {
+            buf.resize(len, 0);
+        }
+
+        let res = unsafe {
+            ffi::tjDecompress2(
+                self.handle,
+                jpeg.as_ptr(),
+                jpeg.len() as c_ulong,
+                buf.as_mut_ptr(),
+                header.width as c_int,
+                0,
+                header.height as c_int,
+                ffi::TJPF_TJPF_RGB as c_int,
+                0,
+            )
+        };
+        if res != 0 {
+            return Err(error(self.handle));
+        }
+        Ok((header.width, header.height))
+    }
+
+    /// Decodes a JPEG image into a new [`Frame`]
+    pub fn decode(&mut self, jpeg: &[u8]) -> io::Result<Frame> {
+        let mut data = Vec::new();
+        let (width, height) = self.decode_into(jpeg, &mut data)?;
+        Ok(Frame {
+            width,
+            height,
+            sequence: 0,
+            timestamp: Instant::now(),
+            data,
+        })
+    }
+}
+
+impl Drop for Decoder {
+    fn drop(&mut self) {
+        unsafe { ffi::tjDestroy(self.handle) };
+    }
+}
+
+/// Encodes a frame with the given quality and the default subsampling
+pub fn encode(frame: &Frame, quality: u8) -> io::Result<Vec<u8>> {
+    Encoder::new()?.quality(quality).encode(frame)
+}
+
+/// Decodes a JPEG image into a new [`Frame`]
+pub fn decode(jpeg: &[u8]) -> io::Result<Frame> {
+    Decoder::new()?.decode(jpeg)
+}
@@ -1,10 +1,64 @@
+pub mod average;
+
+pub mod bracket;
+
+#[cfg(all(unix, feature = "broker"))]
+pub mod broker;
+
+pub mod calibration;
+
+pub mod camera_set;
+pub use camera_set::CameraSet;
+
 pub mod context;
 
+pub mod control;
+pub use control::Control;
+
+pub mod correction;
+
 pub mod format;
 pub use format::Format;
 
+pub mod exposure;
+
+pub mod focus;
+
+pub mod frame;
+pub use frame::Frame;
+
+pub mod hdr;
+
+#[cfg(target_os = "linux")]
+pub mod jpeg;
+
 pub mod device;
-pub use device::Device;
+pub use device::{Device, DeviceInfo};
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+#[cfg(feature = "http-preview")]
+pub mod preview;
+
+pub mod profile;
+pub use profile::CameraProfile;
+
+pub mod property;
+pub use property::{Property, Value};
+
+pub mod roi;
+pub use roi::Roi;
+
+pub mod selector;
+pub use selector::DeviceSelector;
+
+pub mod stats;
+pub use stats::Stats;
 
 pub mod stream;
 pub use stream::Stream;
+
+pub mod supervisor;
+
+pub mod transform;
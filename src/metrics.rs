@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::device::Device;
+use crate::stats::Stats;
+use crate::stream::Stream;
+use crate::supervisor::{Event, State};
+
+/// Prefix of all metric names
+const PREFIX: &str = "openpnp_capture";
+
+/// Content type of the Prometheus text exposition format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Metrics of one camera
+#[derive(Debug, Default, Clone)]
+struct Camera {
+    /// Latest statistics
+    stats: Stats,
+    /// Frame counter at the last update, it restarts when a stream is reopened
+    last_count: u32,
+    /// Frames captured over all streams of the camera
+    captured: u64,
+    /// Host time at which the frame counter last changed
+    last_frame: Option<Instant>,
+    stalls: u64,
+    reconnects: u64,
+    property_failures: u64,
+    /// The camera delivers frames
+    up: bool,
+}
+
+#[derive(Debug, Default, Clone)]
+/// Camera metrics in the Prometheus text format
+///
+/// Metrics are labelled with the device name and unique ID. Cloned instances share the same
+/// metrics, so one can be handed to [`Metrics::serve`] while the capture loop updates another.
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::metrics::Metrics;
+/// use openpnp_capture::{Device, Stats};
+///
+/// let dev = Device {
+///     index: 0,
+///     name: "HD Pro Webcam C920".to_string(),
+///     id: "usb-0000:00:14.0-2".to_string(),
+/// };
+/// let metrics = Metrics::new();
+/// metrics.observe(&dev, &Stats { fps: 30.0, captured: 120, ..Stats::default() });
+/// metrics.record_property_failure(&dev);
+///
+/// let text = metrics.render();
+/// let labels = r#"{device="HD Pro Webcam C920",unique_id="usb-0000:00:14.0-2"}"#;
+/// assert!(text.contains(&format!("openpnp_capture_frames_captured_total{} 120", labels)));
+/// assert!(text.contains(&format!("openpnp_capture_fps{} 30", labels)));
+/// assert!(text.contains(&format!("openpnp_capture_property_failures_total{} 1", labels)));
+/// ```
+pub struct Metrics {
+    cameras: Arc<Mutex<BTreeMap<(String, String), Camera>>>,
+}
+
+impl Metrics {
+    /// Returns an empty registry
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    fn with_camera<F: FnOnce(&mut Camera)>(&self, dev: &Device, f: F) {
+        let mut cameras = self.cameras.lock().unwrap();
+        let camera = cameras
+            .entry((dev.name.clone(), dev.id.clone()))
+            .or_insert_with(|| Camera {
+                up: true,
+                ..Camera::default()
+            });
+        f(camera)
+    }
+
+    /// Records the statistics of a camera, see [`Stream::stats`]
+    pub fn observe(&self, dev: &Device, stats: &Stats) {
+        self.with_camera(dev, |camera| {
+            // The counter restarts at zero when the stream is reopened. This is reported by
+            // `record_events`, a smaller count catches reopens that were not reported.
+            let new = match stats.captured.checked_sub(camera.last_count) {
+                Some(new) => new,
+                None => stats.captured,
+            };
+            if new > 0 || camera.last_frame.is_none() {
+                camera.last_frame = Some(Instant::now());
+            }
+            camera.captured += new as u64;
+            camera.last_count = stats.captured;
+            camera.stats = *stats;
+        });
+    }
+
+    /// Records the current statistics of a stream
+    pub fn update(&self, dev: &Device, stream: &Stream) {
+        self.observe(dev, &stream.stats());
+    }
+
+    /// Counts stalls, reconnections and restore failures reported by a supervised stream
+    ///
+    /// Record the events before the statistics of the reopened stream, so its frames are
+    /// counted from zero.
+    pub fn record_events(&self, dev: &Device, events: &[Event]) {
+        self.with_camera(dev, |camera| {
+            for event in events {
+                match event {
+                    Event::StateChanged { to, .. } => {
+                        camera.up = *to == State::Running;
+                        match to {
+                            State::Stalled => camera.stalls += 1,
+                            State::Running => {
+                                camera.reconnects += 1;
+                                camera.last_count = 0;
+                            }
+                        }
+                    }
+                    Event::RestoreFailed(_) => camera.property_failures += 1,
+                    Event::ReconnectFailed(_) | Event::SnapshotFailed { .. } => {}
+                }
+            }
+        });
+    }
+
+    /// Counts a property that could not be set
+    pub fn record_property_failure(&self, dev: &Device) {
+        self.with_camera(dev, |camera| camera.property_failures += 1);
+    }
+
+    /// Returns the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let cameras = self.cameras.lock().unwrap();
+        let now = Instant::now();
+
+        type Sample = fn(&Camera, Instant) -> f64;
+        let families: [(&str, &str, &str, Sample); 10] = [
+            (
+                "frames_captured_total",
+                "counter",
+                "Frames produced by the capture thread",
+                |c, _| c.captured as f64,
+            ),
+            ("fps", "gauge", "Measured frame rate", |c, _| c.stats.fps),
+            (
+                "frame_interval_jitter_seconds",
+                "gauge",
+                "Standard deviation of the time between frames",
+                |c, _| c.stats.jitter.as_secs_f64(),
+            ),
+            (
+                "frames_dropped",
+                "gauge",
+                "Frames of the current stream that were never read",
+                |c, _| c.stats.dropped as f64,
+            ),
+            (
+                "frames_duplicate",
+                "gauge",
+                "Reads of the current stream that returned a frame already read",
+                |c, _| c.stats.duplicates as f64,
+            ),
+            (
+                "last_frame_age_seconds",
+                "gauge",
+                "Time since the frame counter last changed",
+                |c, now| {
+                    c.last_frame
+                        .map_or(0.0, |t| now.saturating_duration_since(t).as_secs_f64())
+                },
+            ),
+            (
+                "stalls_total",
+                "counter",
+                "Times the stream stopped delivering frames",
+                |c, _| c.stalls as f64,
+            ),
+            (
+                "reconnects_total",
+                "counter",
+                "Times the stream was reopened after a stall",
+                |c, _| c.reconnects as f64,
+            ),
+            (
+                "property_failures_total",
+                "counter",
+                "Properties that could not be set or restored",
+                |c, _| c.property_failures as f64,
+            ),
+            (
+                "up",
+                "gauge",
+                "Whether the camera delivers frames",
+                |c, _| if c.up { 1.0 } else { 0.0 },
+            ),
+        ];
+
+        let mut out = String::new();
+        for (name, kind, help, sample) in &families {
+            let _ = writeln!(out, "# HELP {}_{} {}", PREFIX, name, help);
+            let _ = writeln!(out, "# TYPE {}_{} {}", PREFIX, name, kind);
+            for ((device, id), camera) in cameras.iter() {
+                let _ = writeln!(
+                    out,
+                    "{}_{}{{device=\"{}\",unique_id=\"{}\"}} {}",
+                    PREFIX,
+                    name,
+                    escape(device),
+                    escape(id),
+                    sample(camera, now)
+                );
+            }
+        }
+        out
+    }
+
+    /// Serves the metrics over HTTP at `/metrics` from a background thread
+    ///
+    /// Returns the bound address, which is useful when binding to port 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::metrics::Metrics;
+    /// use std::io::{Read, Write};
+    /// use std::net::TcpStream;
+    ///
+    /// let metrics = Metrics::new();
+    /// let addr = metrics.serve("127.0.0.1:0").unwrap();
+    ///
+    /// let mut conn = TcpStream::connect(addr).unwrap();
+    /// conn.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    /// let mut response = String::new();
+    /// conn.read_to_string(&mut response).unwrap();
+    /// assert!(response.starts_with("HTTP/1.1 200 OK"));
+    /// assert!(response.contains("# TYPE openpnp_capture_fps gauge"));
+    /// ```
+    pub fn serve<A: ToSocketAddrs>(&self, addr: A) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let metrics = self.clone();
+        thread::spawn(move || {
+            for conn in listener.incoming().flatten() {
+                // A broken scrape must not stop the exporter
+                let _ = metrics.respond(conn);
+            }
+        });
+        Ok(addr)
+    }
+
+    /// Answers a single HTTP request
+    fn respond(&self, mut conn: TcpStream) -> io::Result<()> {
+        conn.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+        // Only the request line matters, read up to the end of the headers
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 8192 {
+            let n = conn.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+
+        let request = String::from_utf8_lossy(&request);
+        let mut parts = request.split_whitespace();
+        let (status, body) = match (parts.next(), parts.next()) {
+            (Some("GET"), Some("/metrics")) | (Some("GET"), Some("/")) => ("200 OK", self.render()),
+            (Some("GET"), _) => ("404 Not Found", "not found\n".to_string()),
+            _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+        };
+
+        write!(
+            conn,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            CONTENT_TYPE,
+            body.len(),
+            body
+        )
+    }
+}
+
+/// Escapes a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,470 @@
+use std::collections::BTreeMap;
+#[cfg(not(target_os = "linux"))]
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(not(target_os = "linux"))]
+use jpeg_encoder::{ColorType, Encoder};
+use serde::Serialize;
+
+use crate::device::Device;
+use crate::format::Format;
+use crate::frame::Frame;
+use crate::stream::Stream;
+
+/// Default highest frame rate sent to a single client
+pub const DEFAULT_MAX_FPS: f64 = 10.0;
+
+/// Default JPEG quality
+pub const DEFAULT_QUALITY: u8 = 80;
+
+/// Boundary between the parts of an MJPEG response
+const BOUNDARY: &str = "frame";
+
+/// Time a client may take to accept data before it is dropped
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize)]
+/// Stream entry of the device listing
+struct StreamInfo {
+    name: String,
+    format: Format,
+    frames: u32,
+}
+
+#[derive(Serialize)]
+/// Body of the device listing
+struct Listing {
+    devices: Vec<Device>,
+    streams: Vec<StreamInfo>,
+}
+
+/// Parsed request line
+struct Request {
+    method: String,
+    path: String,
+    query: String,
+}
+
+#[derive(Debug, Clone)]
+/// Browser preview of open streams over HTTP
+///
+/// Frames are taken with [`Stream::peek_frame`], so previewing does not interfere with the
+/// application reading the stream: [`Stream::poll`] still reports new frames and the
+/// [`Stream::stats`] are unaffected. Every client is served from its own thread and gets at
+/// most [`Preview::max_fps`] frames per second, or less if it asks for it.
+///
+/// The server provides:
+///
+/// * `/` - Page showing all streams
+/// * `/devices` - JSON listing of the devices and the previewed streams
+/// * `/streams/<name>/snapshot.jpg` - Current frame as JPEG
+/// * `/streams/<name>/mjpeg?fps=<fps>` - `multipart/x-mixed-replace` MJPEG stream
+///
+/// Streams are shared with the application through an [`Arc`], so set up orientation and
+/// processing before adding them.
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::preview::Preview;
+/// use openpnp_capture::{Device, Format, Stream};
+/// use std::io::{Read, Write};
+/// use std::net::TcpStream;
+/// use std::sync::Arc;
+///
+/// let preview = Preview::new().max_fps(5.0);
+/// if let Some(dev) = Device::new(0) {
+///     if let Some(stream) = Stream::new(&dev, &Format::default()) {
+///         preview.add("top", Arc::new(stream));
+///     }
+/// }
+/// let addr = preview.serve("127.0.0.1:0").unwrap();
+///
+/// let mut conn = TcpStream::connect(addr).unwrap();
+/// conn.write_all(b"GET /devices HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+/// let mut response = String::new();
+/// conn.read_to_string(&mut response).unwrap();
+/// assert!(response.starts_with("HTTP/1.1 200 OK"));
+/// assert!(response.contains("\"streams\":["));
+///
+/// let mut conn = TcpStream::connect(addr).unwrap();
+/// conn.write_all(b"GET /streams/nonexistent/snapshot.jpg HTTP/1.1\r\n\r\n").unwrap();
+/// let mut response = String::new();
+/// conn.read_to_string(&mut response).unwrap();
+/// assert!(response.starts_with("HTTP/1.1 404 Not Found"));
+/// ```
+pub struct Preview {
+    streams: Arc<Mutex<BTreeMap<String, Arc<Stream>>>>,
+    max_fps: f64,
+    quality: u8,
+}
+
+impl Default for Preview {
+    fn default() -> Self {
+        Preview::new()
+    }
+}
+
+impl Preview {
+    /// Returns a preview without streams
+    pub fn new() -> Self {
+        Preview {
+            streams: Arc::new(Mutex::new(BTreeMap::new())),
+            max_fps: DEFAULT_MAX_FPS,
+            quality: DEFAULT_QUALITY,
+        }
+    }
+
+    /// Builder: sets the highest frame rate sent to a single client
+    pub fn max_fps(mut self, fps: f64) -> Self {
+        self.max_fps = fps;
+        self
+    }
+
+    /// Builder: sets the JPEG quality, from 1 to 100
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.quality = quality.clamp(1, 100);
+        self
+    }
+
+    /// Adds a stream under a name, replacing and returning a stream of the same name
+    ///
+    /// Streams can be added and removed while serving, the server and all clones share them.
+    pub fn add(&self, name: &str, stream: Arc<Stream>) -> Option<Arc<Stream>> {
+        self.streams
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), stream)
+    }
+
+    /// Removes a stream
+    ///
+    /// Clients watching it are disconnected with their next frame.
+    pub fn remove(&self, name: &str) -> Option<Arc<Stream>> {
+        self.streams.lock().unwrap().remove(name)
+    }
+
+    /// Returns the names of the streams
+    pub fn names(&self) -> Vec<String> {
+        self.streams.lock().unwrap().keys().cloned().collect()
+    }
+
+    fn stream(&self, name: &str) -> Option<Arc<Stream>> {
+        self.streams.lock().unwrap().get(name).cloned()
+    }
+
+    /// Serves the preview over HTTP from background threads
+    ///
+    /// Returns the bound address, which is useful when binding to port 0.
+    pub fn serve<A: ToSocketAddrs>(&self, addr: A) -> io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let preview = self.clone();
+        thread::spawn(move || {
+            for conn in listener.incoming().flatten() {
+                let preview = preview.clone();
+                thread::spawn(move || {
+                    // A client that goes away must not stop the server
+                    let _ = preview.respond(conn);
+                });
+            }
+        });
+        Ok(addr)
+    }
+
+    /// Answers a single HTTP request
+    fn respond(&self, mut conn: TcpStream) -> io::Result<()> {
+        conn.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        conn.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+        let request = match read_request(&mut conn)? {
+            Some(request) => request,
+            None => return Ok(()),
+        };
+        if request.method != "GET" {
+            return send(
+                &mut conn,
+                "405 Method Not Allowed",
+                "text/plain",
+                b"method not allowed\n",
+            );
+        }
+
+        let segments: Vec<String> = request
+            .path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(percent_decode)
+            .collect();
+        let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+
+        match segments.as_slice() {
+            [] => send(
+                &mut conn,
+                "200 OK",
+                "text/html; charset=utf-8",
+                self.index().as_bytes(),
+            ),
+            ["devices"] => match serde_json::to_vec(&self.listing()) {
+                Ok(body) => send(&mut conn, "200 OK", "application/json", &body),
+                Err(e) => Err(io::Error::other(e)),
+            },
+            ["streams", name, "snapshot.jpg"] => match self.stream(name) {
+                Some(stream) => match stream
+                    .peek_frame()
+                    .and_then(|frame| FrameEncoder::new(self.quality)?.encode(&frame))
+                {
+                    Ok(jpeg) => send(&mut conn, "200 OK", "image/jpeg", &jpeg),
+                    Err(e) => {
+                        let body = format!("{}\n", e);
+                        send(
+                            &mut conn,
+                            "503 Service Unavailable",
+                            "text/plain",
+                            body.as_bytes(),
+                        )
+                    }
+                },
+                None => not_found(&mut conn),
+            },
+            ["streams", name, "mjpeg"] => match self.stream(name) {
+                Some(_) => {
+                    let fps = query_value(&request.query, "fps")
+                        .and_then(|fps| fps.parse::<f64>().ok())
+                        .filter(|fps| *fps > 0.0)
+                        .map_or(self.max_fps, |fps| fps.min(self.max_fps));
+                    self.stream_mjpeg(&mut conn, name, fps)
+                }
+                None => not_found(&mut conn),
+            },
+            _ => not_found(&mut conn),
+        }
+    }
+
+    /// Sends new frames of a stream as MJPEG until the client goes away or the stream is removed
+    fn stream_mjpeg(&self, conn: &mut TcpStream, name: &str, fps: f64) -> io::Result<()> {
+        write!(
+            conn,
+            "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={}\r\n\
+             Cache-Control: no-cache\r\nConnection: close\r\n\r\n",
+            BOUNDARY
+        )?;
+
+        let interval = Duration::from_secs_f64(1.0 / fps.max(0.001));
+        let mut encoder = FrameEncoder::new(self.quality)?;
+        let mut last: Option<u32> = None;
+        loop {
+            let started = Instant::now();
+            let stream = match self.stream(name) {
+                Some(stream) => stream,
+                None => return Ok(()),
+            };
+
+            // Only send frames the client has not seen yet
+            if last != Some(stream.frame_count()) {
+                let frame = stream.peek_frame()?;
+                drop(stream);
+                let jpeg = encoder.encode(&frame)?;
+                write!(
+                    conn,
+                    "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+                    BOUNDARY,
+                    jpeg.len()
+                )?;
+                conn.write_all(&jpeg)?;
+                conn.write_all(b"\r\n")?;
+                conn.flush()?;
+                last = Some(frame.sequence);
+                thread::sleep(interval.saturating_sub(started.elapsed()));
+            } else {
+                drop(stream);
+                thread::sleep(Duration::from_millis(5));
+            }
+        }
+    }
+
+    /// Returns the device listing
+    fn listing(&self) -> Listing {
+        let devices = Device::enumerate()
+            .into_iter()
+            .filter_map(Device::new)
+            .collect();
+        let streams = self
+            .streams
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, stream)| StreamInfo {
+                name: name.clone(),
+                format: stream.format(),
+                frames: stream.frame_count(),
+            })
+            .collect();
+        Listing { devices, streams }
+    }
+
+    /// Returns the page showing all streams
+    fn index(&self) -> String {
+        let mut page = String::from(
+            "<!DOCTYPE html>\n<html>\n<head><title>Camera preview</title></head>\n<body>\n",
+        );
+        for name in self.names() {
+            page += &format!(
+                "<figure><img src=\"/streams/{}/mjpeg\"><figcaption>{}</figcaption></figure>\n",
+                percent_encode(&name),
+                html_escape(&name)
+            );
+        }
+        page += "</body>\n</html>\n";
+        page
+    }
+}
+
+/// JPEG encoder of a client, kept for all frames sent to it
+#[cfg(target_os = "linux")]
+struct FrameEncoder(crate::jpeg::Encoder);
+
+#[cfg(target_os = "linux")]
+impl FrameEncoder {
+    fn new(quality: u8) -> io::Result<Self> {
+        Ok(FrameEncoder(crate::jpeg::Encoder::new()?.quality(quality)))
+    }
+
+    /// Encodes an RGB24 frame as JPEG
+    fn encode(&mut self, frame: &Frame) -> io::Result<Vec<u8>> {
+        self.0.encode(frame)
+    }
+}
+
+/// JPEG encoder of a client, kept for all frames sent to it
+#[cfg(not(target_os = "linux"))]
+struct FrameEncoder(u8);
+
+#[cfg(not(target_os = "linux"))]
+impl FrameEncoder {
+    fn new(quality: u8) -> io::Result<Self> {
+        Ok(FrameEncoder(quality))
+    }
+
+    /// Encodes an RGB24 frame as JPEG
+    fn encode(&mut self, frame: &Frame) -> io::Result<Vec<u8>> {
+        let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "frame too large for JPEG");
+        let width = u16::try_from(frame.width).map_err(|_| too_large())?;
+        let height = u16::try_from(frame.height).map_err(|_| too_large())?;
+
+        let mut jpeg = Vec::new();
+        Encoder::new(&mut jpeg, self.0)
+            .encode(&frame.data, width, height, ColorType::Rgb)
+            .map_err(io::Error::other)?;
+        Ok(jpeg)
+    }
+}
+
+/// Reads the request line and skips the headers
+///
+/// Returns `None` if the client closed the connection without sending a request.
+fn read_request(conn: &mut TcpStream) -> io::Result<Option<Request>> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 8192 {
+        let n = conn.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut parts = request.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method, target),
+        _ => return Ok(None),
+    };
+    let (path, query) = match target.find('?') {
+        Some(i) => (&target[..i], &target[i + 1..]),
+        None => (target, ""),
+    };
+    Ok(Some(Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+    }))
+}
+
+/// Sends a complete response
+fn send(conn: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        conn,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-cache\r\n\
+         Connection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    conn.write_all(body)?;
+    conn.flush()
+}
+
+fn not_found(conn: &mut TcpStream) -> io::Result<()> {
+    send(conn, "404 Not Found", "text/plain", b"not found\n")
+}
+
+/// Returns the value of a query parameter
+fn query_value(query: &str, key: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, value)| percent_decode(value))
+}
+
+/// Decodes `%XX` escapes of a URL component
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Escapes a URL path segment
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Escapes text for HTML
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::io;
+
+#[cfg(feature = "profile")]
+use std::{fs, path::Path};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::format::Format;
+use crate::property::{Property, Value};
+use crate::selector::DeviceSelector;
+use crate::stream::Stream;
+
+/// Order in which manual property values are applied
+///
+/// Exposure and gain are set before the properties derived from the image brightness, and zoom
+/// is set before focus since moving the zoom lens shifts the focal plane.
+pub const APPLY_ORDER: [Property; 13] = [
+    Property::PowerLineFrequency,
+    Property::Exposure,
+    Property::Gain,
+    Property::WhiteBalance,
+    Property::Brightness,
+    Property::Contrast,
+    Property::Saturation,
+    Property::Gamma,
+    Property::Hue,
+    Property::Sharpness,
+    Property::BacklightCompensation,
+    Property::Zoom,
+    Property::Focus,
+];
+
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Saved camera setup
+///
+/// A profile is usually taken from a tuned stream with [`Stream::snapshot_profile`] and restored
+/// with [`Stream::apply_profile`].
+///
+/// # Example
+///
+/// ```
+/// # #[cfg(feature = "profile")]
+/// # {
+/// use openpnp_capture::profile::CameraProfile;
+/// use openpnp_capture::{DeviceSelector, Format, Property, Value};
+///
+/// let profile = CameraProfile::default()
+///     .selector(DeviceSelector::new().usb_id(0x046d, 0x0825).serial("A1B2C3"))
+///     .format(Format::default().width(1280).height(720).fps(30))
+///     .property(Property::Exposure, Value::Manual(-6))
+///     .property(Property::WhiteBalance, Value::Auto);
+/// let toml = profile.to_toml().unwrap();
+/// assert!(toml.contains("exposure = -6"));
+/// assert!(toml.contains(r#"whitebalance = "auto""#));
+/// assert_eq!(CameraProfile::from_toml(&toml).unwrap(), profile);
+/// # }
+/// ```
+pub struct CameraProfile {
+    /// Device the profile belongs to
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub selector: Option<DeviceSelector>,
+    /// Capture format
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub format: Option<Format>,
+    /// Property settings
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub properties: BTreeMap<Property, Value>,
+}
+
+impl CameraProfile {
+    /// Builder: sets the device selector
+    pub fn selector(mut self, selector: DeviceSelector) -> Self {
+        self.selector = Some(selector);
+        self
+    }
+
+    /// Builder: sets the capture format
+    pub fn format(mut self, format: Format) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Builder: sets a property
+    pub fn property(mut self, prop: Property, value: Value) -> Self {
+        self.properties.insert(prop, value);
+        self
+    }
+
+    /// Resolves the device selector and opens a stream in the profile format
+    ///
+    /// Properties are not applied, see [`Stream::apply_profile`].
+    pub fn open(&self) -> io::Result<Stream> {
+        let selector = self.selector.as_ref().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "profile has no device selector",
+            )
+        })?;
+        let dev = selector.resolve()?;
+        let format = self.format.unwrap_or_default();
+        Stream::new(&dev, &format)
+            .ok_or_else(|| io::Error::other(format!("failed to open stream on {}", dev.name)))
+    }
+
+    /// Parses a profile from TOML
+    #[cfg(feature = "profile")]
+    pub fn from_toml(s: &str) -> io::Result<Self> {
+        toml::from_str(s).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Returns the profile as TOML
+    #[cfg(feature = "profile")]
+    pub fn to_toml(&self) -> io::Result<String> {
+        toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Loads a profile from a TOML file
+    #[cfg(feature = "profile")]
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        CameraProfile::from_toml(&fs::read_to_string(path)?)
+    }
+
+    /// Saves the profile to a TOML file
+    #[cfg(feature = "profile")]
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_toml()?)
+    }
+}
+
+#[derive(Debug)]
+/// Property that could not be restored from a profile
+pub struct Failure {
+    /// Property
+    pub property: Property,
+    /// Value that was supposed to be set
+    pub value: Value,
+    /// Error reported by the driver
+    pub error: io::Error,
+}
+
+/// Returns a file name for a device unique ID that is valid on all platforms
+///
+/// ASCII letters and digits are kept, every other byte is written as `_` followed by two hex
+/// digits. The mapping is reversible, so different IDs never share a file.
+#[cfg(feature = "profile")]
+pub(crate) fn file_name(unique_id: &str) -> String {
+    let mut name = String::with_capacity(unique_id.len());
+    for byte in unique_id.bytes() {
+        if byte.is_ascii_alphanumeric() {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("_{:02X}", byte));
+        }
+    }
+    name
+}
@@ -0,0 +1,384 @@
+use openpnp_capture_sys as ffi;
+use std::{fmt, io, str};
+
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+#[cfg(feature = "serde")]
+use std::convert::TryFrom;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Ord, PartialOrd)]
+/// Camera property
+pub enum Property {
+    /// Exposure time
+    Exposure,
+    /// Focus distance
+    Focus,
+    /// Zoom level
+    Zoom,
+    /// White balance temperature
+    WhiteBalance,
+    /// Sensor gain
+    Gain,
+    /// Brightness
+    Brightness,
+    /// Contrast
+    Contrast,
+    /// Saturation
+    Saturation,
+    /// Gamma
+    Gamma,
+    /// Hue
+    Hue,
+    /// Sharpness
+    Sharpness,
+    /// Backlight compensation
+    BacklightCompensation,
+    /// Power line frequency (anti-flicker)
+    PowerLineFrequency,
+}
+
+impl Property {
+    /// Returns all known properties
+    pub fn all() -> &'static [Property] {
+        &[
+            Property::Exposure,
+            Property::Focus,
+            Property::Zoom,
+            Property::WhiteBalance,
+            Property::Gain,
+            Property::Brightness,
+            Property::Contrast,
+            Property::Saturation,
+            Property::Gamma,
+            Property::Hue,
+            Property::Sharpness,
+            Property::BacklightCompensation,
+            Property::PowerLineFrequency,
+        ]
+    }
+
+    /// Returns the property ID as understood by the C library
+    pub fn id(&self) -> ffi::CapPropertyID {
+        match self {
+            Property::Exposure => ffi::CAPPROPID_EXPOSURE,
+            Property::Focus => ffi::CAPPROPID_FOCUS,
+            Property::Zoom => ffi::CAPPROPID_ZOOM,
+            Property::WhiteBalance => ffi::CAPPROPID_WHITEBALANCE,
+            Property::Gain => ffi::CAPPROPID_GAIN,
+            Property::Brightness => ffi::CAPPROPID_BRIGHTNESS,
+            Property::Contrast => ffi::CAPPROPID_CONTRAST,
+            Property::Saturation => ffi::CAPPROPID_SATURATION,
+            Property::Gamma => ffi::CAPPROPID_GAMMA,
+            Property::Hue => ffi::CAPPROPID_HUE,
+            Property::Sharpness => ffi::CAPPROPID_SHARPNESS,
+            Property::BacklightCompensation => ffi::CAPPROPID_BACKLIGHTCOMP,
+            Property::PowerLineFrequency => ffi::CAPPROPID_POWERLINEFREQ,
+        }
+    }
+
+    /// Returns the lowercase name of the property
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::Property;
+    /// assert_eq!(Property::WhiteBalance.name(), "whitebalance");
+    /// assert_eq!("whitebalance".parse::<Property>().unwrap(), Property::WhiteBalance);
+    /// ```
+    pub fn name(&self) -> &'static str {
+        match self {
+            Property::Exposure => "exposure",
+            Property::Focus => "focus",
+            Property::Zoom => "zoom",
+            Property::WhiteBalance => "whitebalance",
+            Property::Gain => "gain",
+            Property::Brightness => "brightness",
+            Property::Contrast => "contrast",
+            Property::Saturation => "saturation",
+            Property::Gamma => "gamma",
+            Property::Hue => "hue",
+            Property::Sharpness => "sharpness",
+            Property::BacklightCompensation => "backlightcomp",
+            Property::PowerLineFrequency => "powerlinefreq",
+        }
+    }
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl str::FromStr for Property {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        Property::all()
+            .iter()
+            .find(|prop| prop.name() == s)
+            .copied()
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unknown property: {}", s),
+                )
+            })
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Value range of a property
+pub struct Limits {
+    /// Minimum value
+    pub min: i32,
+    /// Maximum value
+    pub max: i32,
+    /// Default value
+    pub default: i32,
+    /// Distance between valid values, 0 if unknown
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub step: i32,
+}
+
+impl Limits {
+    /// Clamps a value into the range
+    pub fn clamp(&self, value: i32) -> i32 {
+        value.max(self.min).min(self.max)
+    }
+
+    /// Clamps a value into the range and rounds it to the nearest step
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::property::Limits;
+    ///
+    /// let limits = Limits { min: 3, max: 2047, default: 250, step: 4 };
+    /// assert_eq!(limits.round(100), 99);
+    /// assert_eq!(limits.round(102), 103);
+    /// assert_eq!(limits.round(5000), 2047);
+    /// ```
+    pub fn round(&self, value: i32) -> i32 {
+        let value = self.clamp(value);
+        if self.step <= 1 {
+            return value;
+        }
+
+        let step = self.step as i64;
+        let offset = value as i64 - self.min as i64;
+        let rounded = self.min as i64 + (offset + step / 2) / step * step;
+        if rounded > self.max as i64 {
+            (rounded - step) as i32
+        } else {
+            rounded as i32
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// How a driver handled a property value
+pub enum Status {
+    /// The value was applied as requested
+    Exact,
+    /// The value was out of range and the driver applied the nearest limit
+    Clamped,
+    /// The driver applied a nearby value, usually the nearest step
+    Rounded,
+    /// The driver kept the previous value
+    Ignored,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// Property value as actually applied by the driver
+pub struct Applied {
+    /// Requested value
+    pub requested: i32,
+    /// Value read back from the driver
+    pub value: i32,
+    /// Classification of the difference
+    pub status: Status,
+}
+
+impl Applied {
+    /// Classifies a value read back after setting a property
+    ///
+    /// # Arguments
+    ///
+    /// * `limits` - Limits of the property
+    /// * `previous` - Value before the property was set
+    /// * `requested` - Requested value
+    /// * `value` - Value read back after the property was set
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::property::{Applied, Limits, Status};
+    ///
+    /// let limits = Limits { min: 3, max: 2047, default: 250, step: 1 };
+    /// assert_eq!(Applied::classify(&limits, 250, 100, 100).status, Status::Exact);
+    /// assert_eq!(Applied::classify(&limits, 250, 5000, 2047).status, Status::Clamped);
+    /// assert_eq!(Applied::classify(&limits, 250, 101, 100).status, Status::Rounded);
+    /// assert_eq!(Applied::classify(&limits, 250, 100, 250).status, Status::Ignored);
+    ///
+    /// // Landing on the nearest step counts as rounded, even if that was the previous value
+    /// let limits = Limits { min: 3, max: 2047, default: 250, step: 4 };
+    /// assert_eq!(Applied::classify(&limits, 99, 100, 99).status, Status::Rounded);
+    /// assert_eq!(Applied::classify(&limits, 251, 100, 251).status, Status::Ignored);
+    /// ```
+    pub fn classify(limits: &Limits, previous: i32, requested: i32, value: i32) -> Self {
+        let status = if value == requested {
+            Status::Exact
+        } else if requested != limits.clamp(requested) && value == limits.clamp(requested) {
+            Status::Clamped
+        } else if value == limits.round(requested) {
+            Status::Rounded
+        } else if value == previous {
+            Status::Ignored
+        } else {
+            Status::Rounded
+        };
+
+        Applied {
+            requested,
+            value,
+            status,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+/// Setting of a property
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::property::Value;
+/// assert_eq!("auto".parse::<Value>().unwrap(), Value::Auto);
+/// assert_eq!("-6".parse::<Value>().unwrap(), Value::Manual(-6));
+/// assert_eq!(Value::Manual(100).to_string(), "100");
+///
+/// # #[cfg(feature = "serde")]
+/// # {
+/// use openpnp_capture::Property;
+/// use std::collections::BTreeMap;
+///
+/// let mut settings = BTreeMap::new();
+/// settings.insert(Property::Exposure, Value::Manual(-6));
+/// settings.insert(Property::Focus, Value::Auto);
+/// let json = serde_json::to_string(&settings).unwrap();
+/// assert_eq!(json, r#"{"exposure":-6,"focus":"auto"}"#);
+/// assert_eq!(serde_json::from_str::<BTreeMap<Property, Value>>(&json).unwrap(), settings);
+/// # }
+/// ```
+pub enum Value {
+    /// Manual value, the automatic mode is disabled
+    Manual(i32),
+    /// Automatic mode
+    Auto,
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Manual(value) => write!(f, "{}", value),
+            Value::Auto => write!(f, "auto"),
+        }
+    }
+}
+
+impl str::FromStr for Value {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Value::Auto);
+        }
+        s.parse::<i32>().map(Value::Manual).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid property value: {}", s),
+            )
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Property {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Property {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let string = String::deserialize(deserializer)?;
+        string.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Value {
+    /// Serializes manual values as integer and the automatic mode as `"auto"`
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Manual(value) => serializer.serialize_i32(*value),
+            Value::Auto => serializer.serialize_str("auto"),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Value;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "an integer or \"auto\"")
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Value, E> {
+                i32::try_from(v)
+                    .map(Value::Manual)
+                    .map_err(|_| E::custom("property value out of range"))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Value, E> {
+                i32::try_from(v)
+                    .map(Value::Manual)
+                    .map_err(|_| E::custom("property value out of range"))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Value, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+/// Converts a C library result code into an io::Result
+pub(crate) fn result(res: ffi::CapResult) -> io::Result<()> {
+    match res {
+        ffi::CAPRESULT_OK => Ok(()),
+        ffi::CAPRESULT_PROPERTYNOTSUPPORTED => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "property not supported",
+        )),
+        ffi::CAPRESULT_DEVICENOTFOUND => {
+            Err(io::Error::new(io::ErrorKind::NotFound, "device not found"))
+        }
+        ffi::CAPRESULT_FORMATNOTSUPPORTED => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "format not supported",
+        )),
+        _ => Err(io::Error::other("res != CAPRESULT_OK")),
+    }
+}
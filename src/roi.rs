@@ -0,0 +1,84 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Rectangular region of interest inside a frame
+pub struct Roi {
+    /// Horizontal offset of the left edge in pixels
+    pub x: u32,
+    /// Vertical offset of the top edge in pixels
+    pub y: u32,
+    /// Width in pixels
+    pub width: u32,
+    /// Height in pixels
+    pub height: u32,
+}
+
+impl Roi {
+    /// Returns a region of interest
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - Horizontal offset of the left edge
+    /// * `y` - Vertical offset of the top edge
+    /// * `width` - Width in pixels
+    /// * `height` - Height in pixels
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Roi {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Returns a region covering a whole frame
+    pub fn full(width: u32, height: u32) -> Self {
+        Roi::new(0, 0, width, height)
+    }
+
+    /// Returns a region of the given size centered in a frame
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::Roi;
+    /// let roi = Roi::centered(200, 200, 1280, 720);
+    /// assert_eq!(roi, Roi::new(540, 260, 200, 200));
+    /// ```
+    pub fn centered(width: u32, height: u32, frame_width: u32, frame_height: u32) -> Self {
+        Roi::new(
+            frame_width.saturating_sub(width) / 2,
+            frame_height.saturating_sub(height) / 2,
+            width,
+            height,
+        )
+        .clip(frame_width, frame_height)
+    }
+
+    /// Returns the part of the region that lies inside a frame of the given size
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::Roi;
+    /// let roi = Roi::new(600, 400, 200, 200).clip(640, 480);
+    /// assert_eq!(roi, Roi::new(600, 400, 40, 80));
+    /// ```
+    pub fn clip(&self, width: u32, height: u32) -> Self {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+        Roi {
+            x,
+            y,
+            width: self.width.min(width - x),
+            height: self.height.min(height - y),
+        }
+    }
+
+    /// Returns true if the region does not contain any pixels
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
@@ -0,0 +1,394 @@
+use std::{error, fmt, io};
+
+use regex::Regex;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::device::{Device, DeviceInfo};
+use crate::format::{Format, FourCC};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+/// Rule a device has to satisfy to be selected
+pub enum Rule {
+    /// USB serial number
+    Serial(String),
+    /// USB vendor and product ID
+    UsbId {
+        /// Vendor ID
+        vendor: u16,
+        /// Product ID
+        product: u16,
+    },
+    /// Regular expression matched against the device name
+    Name(String),
+    /// Bus location, e.g. `usb-0000:00:14.0-2`
+    Bus(String),
+    /// Supports a format of at least the given size and optionally pixelformat
+    Format {
+        /// Minimum width in pixels
+        width: u32,
+        /// Minimum height in pixels
+        height: u32,
+        /// Four character code, e.g. `MJPG`
+        #[cfg_attr(feature = "serde", serde(default))]
+        fourcc: Option<String>,
+    },
+}
+
+impl Rule {
+    /// Returns true if a device satisfies the rule
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Device name
+    /// * `info` - Extended device descriptor
+    /// * `formats` - Formats supported by the device
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::selector::Rule;
+    /// use openpnp_capture::DeviceInfo;
+    ///
+    /// let info = DeviceInfo {
+    ///     serial: Some("A1B2C3".to_string()),
+    ///     ..DeviceInfo::default()
+    /// };
+    /// let rule = Rule::Name("^HD Pro Webcam".to_string());
+    /// assert!(rule.matches("HD Pro Webcam C920", &info, &[]).unwrap());
+    /// let rule = Rule::Name("HD Pro Webcam C9[0-9]+".to_string());
+    /// assert!(rule.matches("HD Pro Webcam C920", &info, &[]).unwrap());
+    /// let rule = Rule::Name("^Webcam".to_string());
+    /// assert!(!rule.matches("HD Pro Webcam C920", &info, &[]).unwrap());
+    /// let rule = Rule::Serial("A1B2C3".to_string());
+    /// assert!(rule.matches("HD Pro Webcam C920", &info, &[]).unwrap());
+    /// ```
+    pub fn matches(&self, name: &str, info: &DeviceInfo, formats: &[Format]) -> io::Result<bool> {
+        Ok(self.compile()?.matches(name, info, formats))
+    }
+
+    /// Parses the pattern and FourCC of the rule, so they can be matched against many devices
+    fn compile(&self) -> io::Result<Compiled<'_>> {
+        let mut compiled = Compiled {
+            rule: self,
+            name: None,
+            fourcc: None,
+        };
+        match self {
+            Rule::Name(pattern) => {
+                let regex = Regex::new(pattern)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+                compiled.name = Some(regex);
+            }
+            Rule::Format {
+                fourcc: Some(fourcc),
+                ..
+            } => compiled.fourcc = Some(fourcc.parse::<FourCC>()?),
+            _ => {}
+        }
+        Ok(compiled)
+    }
+}
+
+/// Rule with its name pattern and FourCC parsed
+struct Compiled<'a> {
+    rule: &'a Rule,
+    name: Option<Regex>,
+    fourcc: Option<FourCC>,
+}
+
+impl Compiled<'_> {
+    fn matches(&self, name: &str, info: &DeviceInfo, formats: &[Format]) -> bool {
+        match self.rule {
+            Rule::Serial(serial) => info.serial.as_deref() == Some(serial.as_str()),
+            Rule::UsbId { vendor, product } => {
+                info.vendor_id == Some(*vendor) && info.product_id == Some(*product)
+            }
+            Rule::Name(_) => self.name.as_ref().is_some_and(|regex| regex.is_match(name)),
+            Rule::Bus(bus) => info.bus == *bus,
+            Rule::Format { width, height, .. } => formats.iter().any(|fmt| {
+                fmt.width >= *width
+                    && fmt.height >= *height
+                    && self.fourcc.map_or(true, |fourcc| fmt.fourcc == fourcc)
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::Serial(serial) => write!(f, "serial = {}", serial),
+            Rule::UsbId { vendor, product } => write!(f, "usb id = {:04x}:{:04x}", vendor, product),
+            Rule::Name(pattern) => write!(f, "name =~ /{}/", pattern),
+            Rule::Bus(bus) => write!(f, "bus = {}", bus),
+            Rule::Format {
+                width,
+                height,
+                fourcc,
+            } => {
+                write!(f, "format >= {}x{}", width, height)?;
+                if let Some(fourcc) = fourcc {
+                    write!(f, " {}", fourcc)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Prioritized list of rules identifying a single device
+///
+/// Rules are applied in order, each one narrowing down the remaining candidates, and a device
+/// has to satisfy all of them. A rule that matches none of the remaining candidates fails the
+/// selection, so a missing camera is never replaced by a similar one. Put the most specific rules
+/// first, the error then reports the most meaningful reason a device was rejected.
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::context::Context;
+/// use openpnp_capture::selector::DeviceSelector;
+///
+/// let selector = DeviceSelector::new()
+///     .usb_id(0x046d, 0x0825)
+///     .serial("A1B2C3");
+/// match Context::resolve(&selector) {
+///     Ok(dev) => println!("Top camera: {:?}", dev),
+///     Err(e) => println!("{}", e),
+/// }
+/// ```
+///
+/// Selectors can be stored along with other settings:
+///
+/// ```
+/// # #[cfg(feature = "serde")]
+/// # {
+/// use openpnp_capture::selector::{DeviceSelector, Rule};
+///
+/// let selector = DeviceSelector::new()
+///     .usb_id(0x046d, 0x0825)
+///     .serial("A1B2C3")
+///     .name("^HD Pro Webcam")
+///     .bus("usb-0000:00:14.0-2")
+///     .format(1280, 720, Some("MJPG"))
+///     .format(640, 480, None);
+/// let json = serde_json::to_string(&selector).unwrap();
+/// assert_eq!(serde_json::from_str::<DeviceSelector>(&json).unwrap(), selector);
+/// let toml = toml::to_string(&selector).unwrap();
+/// assert_eq!(toml::from_str::<DeviceSelector>(&toml).unwrap(), selector);
+///
+/// let selector: DeviceSelector = toml::from_str(r#"
+///     [[rules]]
+///     usb_id = { vendor = 0x046d, product = 0x0825 }
+///     [[rules]]
+///     format = { width = 1280, height = 720 }
+/// "#).unwrap();
+/// assert_eq!(selector.rules[1], Rule::Format { width: 1280, height: 720, fourcc: None });
+/// # }
+/// ```
+pub struct DeviceSelector {
+    /// Rules in descending priority
+    pub rules: Vec<Rule>,
+}
+
+impl DeviceSelector {
+    /// Returns a selector without rules, matching any device
+    pub fn new() -> Self {
+        DeviceSelector::default()
+    }
+
+    /// Builder: appends a rule
+    pub fn rule(mut self, rule: Rule) -> Self {
+        self.rules.push(rule);
+        self
+    }
+
+    /// Builder: appends a serial number rule
+    pub fn serial(self, serial: &str) -> Self {
+        self.rule(Rule::Serial(serial.to_string()))
+    }
+
+    /// Builder: appends a USB vendor and product ID rule
+    pub fn usb_id(self, vendor: u16, product: u16) -> Self {
+        self.rule(Rule::UsbId { vendor, product })
+    }
+
+    /// Builder: appends a device name rule
+    pub fn name(self, pattern: &str) -> Self {
+        self.rule(Rule::Name(pattern.to_string()))
+    }
+
+    /// Builder: appends a bus location rule
+    pub fn bus(self, bus: &str) -> Self {
+        self.rule(Rule::Bus(bus.to_string()))
+    }
+
+    /// Builder: appends a format capability rule
+    pub fn format(self, width: u32, height: u32, fourcc: Option<&str>) -> Self {
+        self.rule(Rule::Format {
+            width,
+            height,
+            fourcc: fourcc.map(|fourcc| fourcc.to_string()),
+        })
+    }
+
+    /// Returns the single device matching the rules
+    ///
+    /// See [`crate::context::Context::resolve`].
+    pub fn resolve(&self) -> Result<Device, ResolveError> {
+        let mut candidates: Vec<Candidate> = Device::enumerate()
+            .into_iter()
+            .filter_map(Device::new)
+            .map(|dev| Candidate {
+                info: dev.info().unwrap_or_default(),
+                formats: dev.formats(),
+                device: dev,
+                rejected_by: None,
+            })
+            .collect();
+
+        let mut remaining: Vec<usize> = (0..candidates.len()).collect();
+        for (i, rule) in self.rules.iter().enumerate() {
+            let compiled = rule.compile().map_err(|e| ResolveError::InvalidRule {
+                rule: rule.clone(),
+                reason: e.to_string(),
+            })?;
+            let mut matched = Vec::new();
+            for index in remaining {
+                let candidate = &mut candidates[index];
+                if compiled.matches(&candidate.device.name, &candidate.info, &candidate.formats) {
+                    matched.push(index);
+                } else {
+                    candidate.rejected_by = Some(i);
+                }
+            }
+
+            if matched.is_empty() {
+                return Err(ResolveError::NoMatch {
+                    rule: Some(rule.clone()),
+                    candidates,
+                });
+            }
+            remaining = matched;
+        }
+
+        match remaining.len() {
+            0 => Err(ResolveError::NoMatch {
+                rule: None,
+                candidates,
+            }),
+            1 => Ok(candidates.swap_remove(remaining[0]).device),
+            _ => Err(ResolveError::Ambiguous { candidates }),
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Device that was considered while resolving a selector
+pub struct Candidate {
+    /// Device
+    pub device: Device,
+    /// Extended device descriptor
+    pub info: DeviceInfo,
+    /// Supported formats
+    pub formats: Vec<Format>,
+    /// Index of the rule that rejected the device, if any
+    pub rejected_by: Option<usize>,
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {} ({})",
+            self.device.index, self.device.name, self.device.id
+        )?;
+        if let Some(serial) = &self.info.serial {
+            write!(f, " serial = {}", serial)?;
+        }
+        if let (Some(vid), Some(pid)) = (self.info.vendor_id, self.info.product_id) {
+            write!(f, " usb id = {:04x}:{:04x}", vid, pid)?;
+        }
+        if let Some(rule) = self.rejected_by {
+            write!(f, ", rejected by rule #{}", rule)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+/// Reason a selector did not resolve to a single device
+pub enum ResolveError {
+    /// A rule matched none of the remaining devices, or there are no devices at all
+    NoMatch {
+        /// Failing rule
+        rule: Option<Rule>,
+        /// All devices that were considered
+        candidates: Vec<Candidate>,
+    },
+    /// More than one device satisfies all rules
+    Ambiguous {
+        /// All devices that were considered
+        candidates: Vec<Candidate>,
+    },
+    /// A rule could not be evaluated, e.g. because of an invalid regular expression
+    InvalidRule {
+        /// Invalid rule
+        rule: Rule,
+        /// Description of the problem
+        reason: String,
+    },
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (candidates, message) = match self {
+            ResolveError::NoMatch {
+                rule: Some(rule),
+                candidates,
+            } => (candidates, format!("no device matches rule '{}'", rule)),
+            ResolveError::NoMatch {
+                rule: None,
+                candidates,
+            } => (candidates, "no device matches".to_string()),
+            ResolveError::Ambiguous { candidates } => {
+                let count = candidates
+                    .iter()
+                    .filter(|c| c.rejected_by.is_none())
+                    .count();
+                (candidates, format!("{} devices match all rules", count))
+            }
+            ResolveError::InvalidRule { rule, reason } => {
+                return write!(f, "invalid rule '{}': {}", rule, reason)
+            }
+        };
+
+        write!(f, "{}", message)?;
+        if candidates.is_empty() {
+            return write!(f, ", no devices found");
+        }
+        write!(f, ", candidates:")?;
+        for candidate in candidates {
+            write!(f, "\n  {}", candidate)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for ResolveError {}
+
+impl From<ResolveError> for io::Error {
+    fn from(err: ResolveError) -> Self {
+        let kind = match err {
+            ResolveError::NoMatch { .. } => io::ErrorKind::NotFound,
+            _ => io::ErrorKind::InvalidInput,
+        };
+        io::Error::new(kind, err.to_string())
+    }
+}
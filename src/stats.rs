@@ -0,0 +1,142 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Number of frame observations the rates are measured over
+pub const WINDOW: usize = 120;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+/// Capture statistics of a stream
+pub struct Stats {
+    /// Frames produced per second, measured over the last [`WINDOW`] new frames read
+    pub fps: f64,
+    /// Mean time between frames over the window
+    pub interval: Duration,
+    /// Standard deviation of the time between frames over the window
+    pub jitter: Duration,
+    /// Frames produced by the capture thread since the stream was opened
+    pub captured: u32,
+    /// Number of reads
+    pub reads: u64,
+    /// Reads that returned a frame that had already been read
+    pub duplicates: u64,
+    /// Frames produced since the first read that were never read
+    pub dropped: u64,
+}
+
+#[derive(Debug, Default, Clone)]
+/// Collects the observations behind [`Stats`]
+///
+/// Frame arrival is observed through the stream frame counter whenever a frame is read, so
+/// rates and intervals are only accurate if frames are read at least as fast as they are
+/// produced. Otherwise the interval of every frame in between is taken to be the same.
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::stats::Tracker;
+/// use std::time::{Duration, Instant};
+///
+/// let start = Instant::now();
+/// let ms = |ms| start + Duration::from_millis(ms);
+///
+/// let mut tracker = Tracker::new();
+/// tracker.record(10, ms(0));
+/// tracker.record(11, ms(40));
+/// tracker.record(11, ms(50)); // same frame again
+/// tracker.record(13, ms(120)); // frame 12 was missed
+///
+/// let stats = tracker.stats(13);
+/// assert_eq!(stats.fps, 25.0);
+/// assert_eq!(stats.interval, Duration::from_millis(40));
+/// assert_eq!(stats.reads, 4);
+/// assert_eq!(stats.duplicates, 1);
+/// assert_eq!(stats.dropped, 1);
+/// ```
+pub struct Tracker {
+    /// Host time and frame counter of the last new frames read
+    samples: VecDeque<(Instant, u32)>,
+    /// Time between frames derived from consecutive samples, in seconds
+    intervals: VecDeque<f64>,
+    /// Frame counter at the first read
+    first: Option<u32>,
+    /// Frame counter at the last read
+    last: Option<u32>,
+    reads: u64,
+    /// Number of distinct frames read
+    unique: u64,
+    duplicates: u64,
+}
+
+impl Tracker {
+    /// Returns an empty tracker
+    pub fn new() -> Self {
+        Tracker::default()
+    }
+
+    /// Records a read at the given host time, `count` is the frame counter of the frame read
+    pub fn record(&mut self, count: u32, now: Instant) {
+        self.reads += 1;
+        self.first.get_or_insert(count);
+        if self.last == Some(count) {
+            self.duplicates += 1;
+            return;
+        }
+        self.last = Some(count);
+        self.unique += 1;
+
+        if let Some(&(then, previous)) = self.samples.back() {
+            let frames = count.wrapping_sub(previous);
+            if frames > 0 {
+                let interval = now.saturating_duration_since(then).as_secs_f64() / frames as f64;
+                for _ in 0..frames.min(WINDOW as u32) {
+                    self.intervals.push_back(interval);
+                }
+                while self.intervals.len() > WINDOW {
+                    self.intervals.pop_front();
+                }
+            }
+        }
+        self.samples.push_back((now, count));
+        if self.samples.len() > WINDOW {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Returns the statistics, `captured` is the current stream frame counter
+    pub fn stats(&self, captured: u32) -> Stats {
+        let fps = match (self.samples.front(), self.samples.back()) {
+            (Some(&(t0, c0)), Some(&(t1, c1))) if t1 > t0 => {
+                c1.wrapping_sub(c0) as f64 / (t1 - t0).as_secs_f64()
+            }
+            _ => 0.0,
+        };
+
+        let n = self.intervals.len().max(1) as f64;
+        let mean = self.intervals.iter().sum::<f64>() / n;
+        let variance = self
+            .intervals
+            .iter()
+            .map(|interval| (interval - mean).powi(2))
+            .sum::<f64>()
+            / n;
+
+        let produced = match (self.first, self.last) {
+            (Some(first), Some(last)) => last.wrapping_sub(first) as u64 + 1,
+            _ => 0,
+        };
+
+        Stats {
+            fps,
+            interval: Duration::from_secs_f64(mean),
+            jitter: Duration::from_secs_f64(variance.sqrt()),
+            captured,
+            reads: self.reads,
+            duplicates: self.duplicates,
+            dropped: produced.saturating_sub(self.unique),
+        }
+    }
+}
@@ -1,9 +1,43 @@
 use openpnp_capture_sys as ffi;
-use std::io;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{fmt, io};
 
+use crate::average::{self, Mode};
+use crate::bracket::{BracketFrame, ExposureSetting};
+use crate::calibration::Remap;
 use crate::context::CONTEXT;
+use crate::control::{Control, Kind, MenuItem, MAX_MENU_ITEMS};
+use crate::correction::Correction;
 use crate::device::Device;
 use crate::format::Format;
+use crate::frame::Frame;
+use crate::profile::{self, CameraProfile, Failure};
+use crate::property::{self, Applied, Limits, Property, Value};
+use crate::roi::Roi;
+use crate::stats::{Stats, Tracker};
+use crate::transform::{Orientation, Scale};
+
+#[derive(Debug, Copy, Clone)]
+/// Frame counter value and receive time belonging to a copied frame
+struct Captured {
+    /// Value of the stream frame counter
+    sequence: u32,
+    /// Host time at which the frame was received from the device
+    timestamp: Instant,
+}
+
+impl Captured {
+    /// Returns the capture info for a frame counter value and a frame age in microseconds
+    fn new(sequence: u32, age: u64) -> Self {
+        let now = Instant::now();
+        Captured {
+            sequence,
+            timestamp: now.checked_sub(Duration::from_micros(age)).unwrap_or(now),
+        }
+    }
+}
 
 #[derive(Debug)]
 /// Capture device
@@ -12,6 +46,30 @@ pub struct Stream {
     id: i32,
     /// Format
     format: Format,
+    /// Orientation applied to captured frames
+    orientation: Orientation,
+    /// Flat-field correction applied after orienting captured frames
+    correction: Option<Correction>,
+    /// Lens undistortion applied after correcting captured frames
+    undistortion: Option<Remap>,
+    /// Observations for the capture statistics
+    stats: Mutex<Tracker>,
+    /// Buffer for device frames that are oriented before being returned, reused across reads
+    scratch: Scratch,
+    /// Buffer for corrected frames that are undistorted before being returned, reused across
+    /// reads
+    corrected: Scratch,
+}
+
+#[derive(Default)]
+/// Reusable frame buffer, only its size is shown when debug printing
+struct Scratch(Mutex<Vec<u8>>);
+
+impl fmt::Debug for Scratch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let len = self.0.lock().map(|buf| buf.len()).unwrap_or(0);
+        write!(f, "Scratch({} bytes)", len)
+    }
 }
 
 impl Stream {
@@ -29,41 +87,41 @@ impl Stream {
     /// }
     /// ```
     pub fn new(dev: &Device, format: &Format) -> Option<Self> {
+        // The index may be stale if the devices were enumerated again since
+        let dev = &dev.refresh()?;
         let context = CONTEXT.lock().unwrap().inner;
 
-        // Look for the best format match
-        let mut matched = (0, Format::default());
-        let formats = dev.formats();
-        for (i, fmt) in formats.iter().enumerate() {
-            // We assume width and height are always set and take into account FPS and FourCC if
-            // requested.
-
-            // Use L2 error metric for width, height and fourcc.
-            // Prefer higher values for fps.
-
-            let mut current = (matched.1.width as i64 - format.width as i64).pow(2)
-                + (matched.1.height as i64 - format.height as i64).pow(2);
-            if format.fps > 0 {
-                current += (matched.1.fps as i64 - format.fps as i64).pow(2);
-            }
-            if format.fourcc.as_u32() > 0 {
-                current +=
-                    (matched.1.fourcc.as_u32() as i64 - format.fourcc.as_u32() as i64).pow(2);
-            }
-
-            let mut candidate = (fmt.width as i64 - format.width as i64).pow(2)
-                + (fmt.height as i64 - format.height as i64).pow(2);
-            if format.fps > 0 {
-                // only penalize smaller fps values
-                if fmt.fps < format.fps {
-                    candidate += (fmt.fps as i64 - format.fps as i64).pow(2);
+        // We assume width and height are always set and take into account FPS and FourCC if
+        // requested.
+        //
+        // Use L2 error metric for width, height and fourcc. For fps, prefer an exact match,
+        // then higher values (penalized linearly), then lower values (penalized quadratically).
+        let error = |fmt: &Format| -> f64 {
+            let mut error = ((fmt.width as i64 - format.width as i64).pow(2)
+                + (fmt.height as i64 - format.height as i64).pow(2))
+                as f64;
+            if !format.fps.is_zero() {
+                let diff = fmt.fps.as_f64() - format.fps.as_f64();
+                if diff >= 0.0 {
+                    error += diff;
+                } else {
+                    error += 4.0 * diff * diff;
                 }
             }
             if format.fourcc.as_u32() > 0 {
-                candidate += (fmt.fourcc.as_u32() as i64 - format.fourcc.as_u32() as i64).pow(2);
+                error += (fmt.fourcc.as_u32() as i64 - format.fourcc.as_u32() as i64).pow(2) as f64;
             }
+            error
+        };
 
-            if candidate < current {
+        // Look for the best format match
+        let mut matched = (0, Format::default());
+        let mut best = f64::MAX;
+        let formats = dev.formats();
+        for (i, fmt) in formats.iter().enumerate() {
+            let candidate = error(fmt);
+            if candidate < best {
+                best = candidate;
                 matched = (i as u32, *fmt);
             }
         }
@@ -79,6 +137,12 @@ impl Stream {
             id => Some(Stream {
                 id,
                 format: matched.1,
+                orientation: Orientation::default(),
+                correction: None,
+                undistortion: None,
+                stats: Mutex::new(Tracker::new()),
+                scratch: Scratch::default(),
+                corrected: Scratch::default(),
             }),
         }
     }
@@ -89,8 +153,87 @@ impl Stream {
     }
 
     /// Returns the format in use
+    ///
+    /// Width and height are those of the frames returned by [`Stream::read`], i.e. they are
+    /// swapped if the orientation rotates by a quarter turn.
     pub fn format(&self) -> Format {
-        self.format
+        let (width, height) = self.orientation.size(self.format.width, self.format.height);
+        Format {
+            width,
+            height,
+            ..self.format
+        }
+    }
+
+    /// Returns the orientation applied to captured frames
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    /// Sets the orientation applied to captured frames, e.g. for a camera mounted sideways
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::transform::{Orientation, Rotation};
+    /// use openpnp_capture::{Device, Format, Stream};
+    ///
+    /// let dev = Device::new(0);
+    /// if let Some(dev) = &dev {
+    ///     if let Some(mut stream) = Stream::new(&dev, &Format::default().width(640).height(480)) {
+    ///         stream.set_orientation(Orientation::new().rotate(Rotation::R90));
+    ///         assert_eq!(stream.format().width, 480);
+    ///     }
+    /// }
+    /// ```
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+
+    /// Returns the flat-field correction applied to captured frames
+    pub fn correction(&self) -> Option<&Correction> {
+        self.correction.as_ref()
+    }
+
+    /// Sets the flat-field correction applied to captured frames, see [`crate::correction`]
+    ///
+    /// The correction is applied after the orientation and before the undistortion, so it has
+    /// to be computed from frames read while neither correction nor undistortion were set.
+    pub fn set_correction(&mut self, correction: Option<Correction>) -> io::Result<()> {
+        if let Some(correction) = &correction {
+            self.check_size(correction.size(), "correction")?;
+        }
+        self.correction = correction;
+        Ok(())
+    }
+
+    /// Returns the lens undistortion applied to captured frames
+    pub fn undistortion(&self) -> Option<&Remap> {
+        self.undistortion.as_ref()
+    }
+
+    /// Sets the lens undistortion applied to captured frames, see [`crate::calibration`]
+    ///
+    /// Undistortion is applied after the orientation, so the remap must be computed for the
+    /// frame size reported by [`Stream::format`].
+    pub fn set_undistortion(&mut self, remap: Option<Remap>) -> io::Result<()> {
+        if let Some(remap) = &remap {
+            self.check_size(remap.size(), "undistortion")?;
+        }
+        self.undistortion = remap;
+        Ok(())
+    }
+
+    /// Checks that a per-pixel operation matches the current frame size
+    fn check_size(&self, size: (u32, u32), what: &str) -> io::Result<()> {
+        let format = self.format();
+        if size != (format.width, format.height) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} does not match frame size", what),
+            ));
+        }
+        Ok(())
     }
 
     /// Returns true when a new frame is available
@@ -105,7 +248,86 @@ impl Stream {
     }
 
     /// Copy the current frame into a buffer
+    ///
+    /// The frame is oriented, corrected and undistorted as set with [`Stream::set_orientation`],
+    /// [`Stream::set_correction`] and [`Stream::set_undistortion`].
     pub fn read(&self, buf: &mut Vec<u8>) -> io::Result<()> {
+        self.read_captured(buf).map(|_| ())
+    }
+
+    /// Copy the current frame into a buffer like [`Stream::read`] and return its capture info
+    fn read_captured(&self, buf: &mut Vec<u8>) -> io::Result<Captured> {
+        let captured = self.read_processed(buf, false)?;
+        self.stats
+            .lock()
+            .unwrap()
+            .record(captured.sequence, Instant::now());
+        Ok(captured)
+    }
+
+    /// Copy the current frame into a new [`Frame`] without marking it as read
+    ///
+    /// The frame is processed like [`Stream::read`], but [`Stream::poll`] still reports it as
+    /// new and it is not counted in the [`Stream::stats`]. Use this to watch a stream that is
+    /// read by someone else, e.g. for a preview.
+    pub fn peek_frame(&self) -> io::Result<Frame> {
+        let mut data = Vec::new();
+        let captured = self.read_processed(&mut data, true)?;
+
+        let format = self.format();
+        Ok(Frame {
+            width: format.width,
+            height: format.height,
+            sequence: captured.sequence,
+            timestamp: captured.timestamp,
+            data,
+        })
+    }
+
+    /// Copy the current frame into a buffer, applying all processing
+    ///
+    /// With `peek` set, the new frame flag of the device is left untouched.
+    fn read_processed(&self, buf: &mut Vec<u8>, peek: bool) -> io::Result<Captured> {
+        if let Some(remap) = &self.undistortion {
+            self.check_size(remap.size(), "undistortion")?;
+            let format = self.format();
+            let mut corrected = self.corrected.0.lock().unwrap();
+            let captured = self.read_corrected(&mut corrected, peek)?;
+            remap.apply_region(&corrected, &Roi::full(format.width, format.height), buf);
+            return Ok(captured);
+        }
+        self.read_corrected(buf, peek)
+    }
+
+    /// Copy the current frame into a buffer, oriented and corrected but not undistorted
+    fn read_corrected(&self, buf: &mut Vec<u8>, peek: bool) -> io::Result<Captured> {
+        let captured = self.read_oriented(buf, peek)?;
+        if let Some(correction) = &self.correction {
+            self.check_size(correction.size(), "correction")?;
+            let format = self.format();
+            correction.apply_region(buf, &Roi::full(format.width, format.height));
+        }
+        Ok(captured)
+    }
+
+    /// Copy the current frame into a buffer, oriented but not undistorted
+    fn read_oriented(&self, buf: &mut Vec<u8>, peek: bool) -> io::Result<Captured> {
+        if self.orientation.is_identity() {
+            return self.read_raw(buf, peek);
+        }
+
+        let mut raw = self.scratch.0.lock().unwrap();
+        let captured = self.read_raw(&mut raw, peek)?;
+        self.orientation
+            .apply(&raw, self.format.width, self.format.height, 3, buf);
+        Ok(captured)
+    }
+
+    /// Copy the current frame into a buffer as delivered by the device
+    ///
+    /// The frame counter value and the receive time are taken together with the copy, so they
+    /// always belong to the copied frame.
+    fn read_raw(&self, buf: &mut Vec<u8>, peek: bool) -> io::Result<Captured> {
         let context = CONTEXT.lock().unwrap().inner;
         let frame_len = (self.format.height * self.format.width * 3/* RGB24 */) as usize;
         if buf.len() != frame_len {
@@ -113,19 +335,744 @@ impl Stream {
         }
 
         // The buffer format is always RGB24
+        let (mut sequence, mut age) = (0, 0);
         let res = unsafe {
-            ffi::Cap_captureFrame(
+            ffi::Cap_captureFrameEx(
                 context,
                 self.id,
                 buf.as_mut_ptr() as *mut std::ffi::c_void,
                 buf.len() as u32,
+                peek as u32,
+                &mut sequence,
+                &mut age,
             )
         };
         match res {
-            ffi::CAPRESULT_OK => Ok(()),
-            _ => Err(io::Error::new(io::ErrorKind::Other, "res != CAPRESULT_OK")),
+            ffi::CAPRESULT_OK => Ok(Captured::new(sequence, age)),
+            _ => Err(io::Error::other("res != CAPRESULT_OK")),
         }
     }
+
+    /// Copy the current frame into a new [`Frame`]
+    pub fn read_frame(&self) -> io::Result<Frame> {
+        let mut data = Vec::new();
+        let captured = self.read_captured(&mut data)?;
+
+        let format = self.format();
+        Ok(Frame {
+            width: format.width,
+            height: format.height,
+            sequence: captured.sequence,
+            timestamp: captured.timestamp,
+            data,
+        })
+    }
+
+    /// Copy a region of the current frame into a buffer
+    ///
+    /// Only the region is copied, so the cost scales with its size instead of the frame size.
+    /// The region is clipped to the frame, the clipped region is returned. Regions are given in
+    /// oriented frame coordinates, see [`Stream::set_orientation`]. If an undistortion is set, the
+    /// whole frame has to be read but only the region is remapped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::{Device, Format, Roi, Stream};
+    ///
+    /// let dev = Device::new(0);
+    /// if let Some(dev) = &dev {
+    ///     if let Some(mut stream) = Stream::new(&dev, &Format::default()) {
+    ///         let format = stream.format();
+    ///         let nozzle = Roi::centered(200, 200, format.width, format.height);
+    ///         let mut buf = Vec::new();
+    ///         stream.advance();
+    ///         if let Ok(roi) = stream.read_roi(&nozzle, &mut buf) {
+    ///             println!("Read {}x{} pixels", roi.width, roi.height);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn read_roi(&self, roi: &Roi, buf: &mut Vec<u8>) -> io::Result<Roi> {
+        let format = self.format();
+        let roi = roi.clip(format.width, format.height);
+        self.read_region(&roi, None, buf)?;
+        Ok(roi)
+    }
+
+    /// Copy a clipped region of the current frame into a buffer, applying all processing
+    ///
+    /// Without orientation, correction and undistortion, the region is scaled while it is
+    /// copied out of the device frame. Returns the capture info and whether the scale was applied.
+    fn read_region(
+        &self,
+        roi: &Roi,
+        scale: Option<Scale>,
+        buf: &mut Vec<u8>,
+    ) -> io::Result<(Captured, bool)> {
+        let direct = self.orientation.is_identity()
+            && self.correction.is_none()
+            && self.undistortion.is_none();
+        let captured = match direct {
+            true => self.read_raw_roi(roi, scale, buf)?,
+            false => self.read_processed_roi(roi, buf)?,
+        };
+        self.stats
+            .lock()
+            .unwrap()
+            .record(captured.sequence, Instant::now());
+        Ok((captured, direct && scale.is_some()))
+    }
+
+    /// Copy a clipped region of the current frame into a buffer, applying all processing
+    fn read_processed_roi(&self, roi: &Roi, buf: &mut Vec<u8>) -> io::Result<Captured> {
+        if let Some(remap) = &self.undistortion {
+            self.check_size(remap.size(), "undistortion")?;
+            let mut corrected = self.corrected.0.lock().unwrap();
+            let captured = self.read_corrected(&mut corrected, false)?;
+            remap.apply_region(&corrected, roi, buf);
+            return Ok(captured);
+        }
+
+        let captured = self.read_oriented_roi(roi, buf)?;
+        if let Some(correction) = &self.correction {
+            self.check_size(correction.size(), "correction")?;
+            correction.apply_region(buf, roi);
+        }
+        Ok(captured)
+    }
+
+    /// Copy a clipped region of the current frame into a buffer, oriented but not corrected
+    fn read_oriented_roi(&self, roi: &Roi, buf: &mut Vec<u8>) -> io::Result<Captured> {
+        if self.orientation.is_identity() {
+            return self.read_raw_roi(roi, None, buf);
+        }
+
+        // Read the matching region of the device frame and orient it on its own
+        let source = self
+            .orientation
+            .source_roi(roi, self.format.width, self.format.height);
+        let mut raw = self.scratch.0.lock().unwrap();
+        let captured = self.read_raw_roi(&source, None, &mut raw)?;
+        self.orientation
+            .apply(&raw, source.width, source.height, 3, buf);
+        Ok(captured)
+    }
+
+    /// Copy a clipped region of the current frame as delivered by the device into a buffer,
+    /// optionally scaling it while copying
+    fn read_raw_roi(
+        &self,
+        roi: &Roi,
+        scale: Option<Scale>,
+        buf: &mut Vec<u8>,
+    ) -> io::Result<Captured> {
+        let context = CONTEXT.lock().unwrap().inner;
+        let (width, height) = match scale {
+            Some(scale) => scale.size(roi.width, roi.height),
+            None => (roi.width, roi.height),
+        };
+        let len = width as usize * height as usize * 3/* RGB24 */;
+        if buf.len() != len {
+            buf.resize(len, 0);
+        }
+
+        let (mode, scale_width, scale_height) = match scale {
+            None => (ffi::CAPSCALE_NONE, 0, 0),
+            Some(Scale::Bin(n)) => (ffi::CAPSCALE_BIN, n.max(1), n.max(1)),
+            Some(Scale::Nearest { width, height }) => (ffi::CAPSCALE_NEAREST, width, height),
+            Some(Scale::Bilinear { width, height }) => (ffi::CAPSCALE_BILINEAR, width, height),
+        };
+        let (mut sequence, mut age) = (0, 0);
+        let res = unsafe {
+            ffi::Cap_captureFrameRegionEx(
+                context,
+                self.id,
+                roi.x,
+                roi.y,
+                roi.width,
+                roi.height,
+                mode,
+                scale_width,
+                scale_height,
+                buf.as_mut_ptr() as *mut std::ffi::c_void,
+                buf.len() as u32,
+                &mut sequence,
+                &mut age,
+            )
+        };
+        match res {
+            ffi::CAPRESULT_OK => Ok(Captured::new(sequence, age)),
+            _ => Err(io::Error::other("res != CAPRESULT_OK")),
+        }
+    }
+
+    /// Copy a region of the current frame into a new [`Frame`], optionally scaling it
+    ///
+    /// See [`Stream::read_roi`] and [`Scale::apply`]. Unless the stream orients, corrects or
+    /// undistorts frames, the scaling is done while copying the region, so only the scaled
+    /// region is ever written.
+    pub fn read_roi_frame(&self, roi: &Roi, scale: Option<Scale>) -> io::Result<Frame> {
+        let format = self.format();
+        let roi = roi.clip(format.width, format.height);
+        let mut data = Vec::new();
+        let (captured, scaled) = self.read_region(&roi, scale, &mut data)?;
+
+        let (width, height) = match (scale, scaled) {
+            (Some(scale), true) => scale.size(roi.width, roi.height),
+            _ => (roi.width, roi.height),
+        };
+        let frame = Frame {
+            width,
+            height,
+            sequence: captured.sequence,
+            timestamp: captured.timestamp,
+            data,
+        };
+        Ok(match (scale, scaled) {
+            (Some(scale), false) => scale.apply(&frame),
+            _ => frame,
+        })
+    }
+
+    /// Returns the first frame whose exposure started after the given instant
+    ///
+    /// A frame is taken to be exposed during the frame interval before it was received, so the
+    /// first frame received at least one interval after `instant` is accepted. The interval
+    /// comes from the frame rate of the format, or from the [`Stream::stats`] if the rate is
+    /// unknown. If `instant` lies in the past, a frame that was already received qualifies as
+    /// well. After that, `settle` additional frames are skipped, e.g. to give auto exposure
+    /// time to adapt.
+    ///
+    /// # Arguments
+    ///
+    /// * `instant` - Point in time the exposure must start after
+    /// * `settle` - Number of additional frames to skip
+    /// * `timeout` - Maximum time to wait, counted from the call
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::{Device, Format, Stream};
+    /// use std::time::{Duration, Instant};
+    ///
+    /// let dev = Device::new(0);
+    /// if let Some(dev) = &dev {
+    ///     if let Some(mut stream) = Stream::new(&dev, &Format::default()) {
+    ///         // ... move the head and wait for it to stop ...
+    ///         let stopped = Instant::now();
+    ///         let frame = stream.capture_after(stopped, 1, Duration::from_secs(1));
+    ///         println!("Frame: {:?}", frame.map(|frame| frame.sequence));
+    ///     }
+    /// }
+    /// ```
+    pub fn capture_after(
+        &mut self,
+        instant: Instant,
+        settle: u32,
+        timeout: Duration,
+    ) -> io::Result<Frame> {
+        let deadline = Instant::now() + timeout;
+        let timed_out = || io::Error::new(io::ErrorKind::TimedOut, "no frame before deadline");
+
+        // Receive time of the first frame exposed after the instant
+        let earliest = instant + self.frame_interval();
+        if earliest > deadline {
+            return Err(timed_out());
+        }
+        let now = Instant::now();
+        if earliest > now {
+            thread::sleep(earliest - now);
+        }
+
+        // Only look at the receive time when the counter shows a new frame, a zero counter
+        // means that nothing was received yet
+        let mut checked = 0;
+        let accepted = loop {
+            let count = self.frame_count();
+            if count != checked {
+                let captured = self.peek_raw()?;
+                if captured.timestamp >= earliest {
+                    break captured.sequence;
+                }
+                checked = captured.sequence;
+            }
+            if Instant::now() >= deadline {
+                return Err(timed_out());
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+
+        while self.frame_count().wrapping_sub(accepted) < settle {
+            if Instant::now() >= deadline {
+                return Err(timed_out());
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        self.read_frame()
+    }
+
+    /// Returns the time between frames from the frame rate of the format, or as measured
+    fn frame_interval(&self) -> Duration {
+        let fps = self.format.fps.as_f64();
+        if fps > 0.0 {
+            return Duration::from_secs_f64(1.0 / fps);
+        }
+        self.stats().interval
+    }
+
+    /// Returns the capture info of the current device frame without marking it as read
+    fn peek_raw(&self) -> io::Result<Captured> {
+        let mut raw = self.scratch.0.lock().unwrap();
+        self.read_raw(&mut raw, true)
+    }
+
+    /// Captures `n` consecutive frames and stacks them into a single low-noise frame
+    ///
+    /// Fails with [`io::ErrorKind::TimedOut`] if the frames did not arrive within `timeout`,
+    /// counted from the call. See [`average::stack`] for details.
+    pub fn capture_averaged(
+        &mut self,
+        n: usize,
+        mode: Mode,
+        timeout: Duration,
+    ) -> io::Result<Frame> {
+        if mode == Mode::Mean && n > average::MAX_FRAMES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "too many frames to average",
+            ));
+        }
+
+        let deadline = Instant::now() + timeout;
+        let mut frames = Vec::with_capacity(n);
+        for _ in 0..n {
+            while !self.poll() {
+                if Instant::now() >= deadline {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "no frame before deadline",
+                    ));
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+            frames.push(self.read_frame()?);
+        }
+
+        average::stack(&frames, mode)
+    }
+
+    /// Captures one frame per exposure setting and restores the original settings afterward
+    ///
+    /// Automatic exposure (and automatic gain, if any setting has a gain) is disabled while
+    /// bracketing. For every setting, the first frame exposed after the change is discarded as
+    /// well, see [`Stream::capture_after`], so each frame is fully exposed with the new setting.
+    /// The original values and automatic modes are restored even if capturing fails.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::bracket::ExposureSetting;
+    /// use openpnp_capture::{Device, Format, Stream};
+    ///
+    /// let dev = Device::new(0);
+    /// if let Some(dev) = &dev {
+    ///     if let Some(mut stream) = Stream::new(&dev, &Format::default()) {
+    ///         let settings = [
+    ///             ExposureSetting::new(50),
+    ///             ExposureSetting::new(200),
+    ///             ExposureSetting::new(800).gain(0),
+    ///         ];
+    ///         if let Ok(bracket) = stream.capture_bracket(&settings) {
+    ///             for frame in &bracket {
+    ///                 println!("Frame {} at {:?}", frame.frame.sequence, frame.applied);
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn capture_bracket(
+        &mut self,
+        settings: &[ExposureSetting],
+    ) -> io::Result<Vec<BracketFrame>> {
+        let with_gain = settings.iter().any(|setting| setting.gain.is_some());
+
+        // Remember the original settings, properties without an automatic mode are manual
+        let exposure = self.property(Property::Exposure)?;
+        let auto_exposure = self.auto_property(Property::Exposure).unwrap_or(false);
+        let gain = if with_gain {
+            Some((
+                self.property(Property::Gain)?,
+                self.auto_property(Property::Gain).unwrap_or(false),
+            ))
+        } else {
+            None
+        };
+
+        let result = self.bracket(settings, with_gain);
+
+        // Restore manual values first, enabling an automatic mode may override them
+        let mut restored = self.set_property(Property::Exposure, exposure);
+        if auto_exposure {
+            restored = restored.and(self.set_auto_property(Property::Exposure, true));
+        }
+        if let Some((gain, auto_gain)) = gain {
+            restored = restored.and(self.set_property(Property::Gain, gain));
+            if auto_gain {
+                restored = restored.and(self.set_auto_property(Property::Gain, true));
+            }
+        }
+
+        let frames = result?;
+        restored.map(|_| frames)
+    }
+
+    /// Applies the settings one after another and captures a frame for each
+    fn bracket(
+        &mut self,
+        settings: &[ExposureSetting],
+        with_gain: bool,
+    ) -> io::Result<Vec<BracketFrame>> {
+        let _ = self.set_auto_property(Property::Exposure, false);
+        if with_gain {
+            let _ = self.set_auto_property(Property::Gain, false);
+        }
+
+        let mut frames = Vec::with_capacity(settings.len());
+        for setting in settings {
+            let mut applied = ExposureSetting::new(
+                self.set_property_checked(Property::Exposure, setting.exposure)?
+                    .value,
+            );
+            if let Some(gain) = setting.gain {
+                applied.gain = Some(self.set_property_checked(Property::Gain, gain)?.value);
+            } else if with_gain {
+                applied.gain = self.property(Property::Gain).ok();
+            }
+
+            let frame = self.capture_after(Instant::now(), 1, Duration::from_secs(5))?;
+            frames.push(BracketFrame {
+                requested: *setting,
+                applied,
+                frame,
+            });
+        }
+
+        Ok(frames)
+    }
+
+    /// Returns the number of frames captured by the stream so far
+    pub fn frame_count(&self) -> u32 {
+        let context = CONTEXT.lock().unwrap().inner;
+        unsafe { ffi::Cap_getStreamFrameCount(context, self.id) }
+    }
+
+    /// Returns the capture statistics of the frames read so far
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::{Device, Format, Stream};
+    ///
+    /// let dev = Device::new(0);
+    /// if let Some(dev) = &dev {
+    ///     if let Some(mut stream) = Stream::new(&dev, &Format::default()) {
+    ///         for _ in 0..30 {
+    ///             stream.advance();
+    ///             let _ = stream.read_frame();
+    ///         }
+    ///         let stats = stream.stats();
+    ///         println!("{:.1} fps, jitter {:?}, {} dropped", stats.fps, stats.jitter, stats.dropped);
+    ///     }
+    /// }
+    /// ```
+    pub fn stats(&self) -> Stats {
+        self.stats.lock().unwrap().stats(self.frame_count())
+    }
+
+    /// Clears the capture statistics
+    pub fn reset_stats(&self) {
+        *self.stats.lock().unwrap() = Tracker::new();
+    }
+
+    /// Returns the value range of a property
+    pub fn property_limits(&self, prop: Property) -> io::Result<Limits> {
+        let context = CONTEXT.lock().unwrap().inner;
+        let mut limits = Limits::default();
+        let res = unsafe {
+            ffi::Cap_getPropertyLimits(
+                context,
+                self.id,
+                prop.id(),
+                &mut limits.min,
+                &mut limits.max,
+                &mut limits.default,
+            )
+        };
+        property::result(res)?;
+
+        // The step is optional, not every platform reports it
+        let mut step = 0;
+        let res = unsafe { ffi::Cap_getPropertyStep(context, self.id, prop.id(), &mut step) };
+        if res == ffi::CAPRESULT_OK {
+            limits.step = step;
+        }
+        Ok(limits)
+    }
+
+    /// Returns the current value of a property
+    pub fn property(&self, prop: Property) -> io::Result<i32> {
+        let context = CONTEXT.lock().unwrap().inner;
+        let mut value = 0;
+        let res = unsafe { ffi::Cap_getProperty(context, self.id, prop.id(), &mut value) };
+        property::result(res).map(|_| value)
+    }
+
+    /// Sets the value of a property
+    ///
+    /// Most drivers ignore manual values while the automatic mode of a property is enabled, see
+    /// [`Stream::set_auto_property`].
+    pub fn set_property(&self, prop: Property, value: i32) -> io::Result<()> {
+        let context = CONTEXT.lock().unwrap().inner;
+        let res = unsafe { ffi::Cap_setProperty(context, self.id, prop.id(), value) };
+        property::result(res)
+    }
+
+    /// Sets the value of a property and reads back the value the driver actually applied
+    ///
+    /// Many drivers silently clamp or round values, or ignore them while the automatic mode of
+    /// the property is enabled. Some drivers apply values asynchronously, in which case the
+    /// change may be reported as [`property::Status::Ignored`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::property::Status;
+    /// use openpnp_capture::{Device, Format, Property, Stream};
+    ///
+    /// let dev = Device::new(0);
+    /// if let Some(dev) = &dev {
+    ///     if let Some(stream) = Stream::new(&dev, &Format::default()) {
+    ///         if let Ok(applied) = stream.set_property_checked(Property::Exposure, 100) {
+    ///             if applied.status != Status::Exact {
+    ///                 println!("Exposure is {} ({:?})", applied.value, applied.status);
+    ///             }
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn set_property_checked(&self, prop: Property, value: i32) -> io::Result<Applied> {
+        let limits = self.property_limits(prop)?;
+        let previous = self.property(prop)?;
+        self.set_property(prop, value)?;
+        let actual = self.property(prop)?;
+        Ok(Applied::classify(&limits, previous, value, actual))
+    }
+
+    /// Returns true when the automatic mode of a property is enabled
+    pub fn auto_property(&self, prop: Property) -> io::Result<bool> {
+        let context = CONTEXT.lock().unwrap().inner;
+        let mut enabled = 0;
+        let res = unsafe { ffi::Cap_getAutoProperty(context, self.id, prop.id(), &mut enabled) };
+        property::result(res).map(|_| enabled != 0)
+    }
+
+    /// Enables or disables the automatic mode of a property
+    pub fn set_auto_property(&self, prop: Property, enabled: bool) -> io::Result<()> {
+        let context = CONTEXT.lock().unwrap().inner;
+        let res = unsafe { ffi::Cap_setAutoProperty(context, self.id, prop.id(), enabled as u32) };
+        property::result(res)
+    }
+
+    /// Returns all controls the driver exposes
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::{Device, Format, Stream};
+    ///
+    /// let dev = Device::new(0);
+    /// if let Some(dev) = &dev {
+    ///     if let Some(stream) = Stream::new(&dev, &Format::default()) {
+    ///         for control in stream.controls() {
+    ///             println!("{:08x} {} [{}, {}]", control.id, control.name, control.min, control.max);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn controls(&self) -> Vec<Control> {
+        let context = CONTEXT.lock().unwrap().inner;
+        let count = unsafe { ffi::Cap_getNumControls(context, self.id) };
+
+        let mut controls = Vec::new();
+        for index in 0..count.max(0) as u32 {
+            let mut info: ffi::CapControlInfo = unsafe { std::mem::zeroed() };
+            let res = unsafe { ffi::Cap_getControlInfo(context, self.id, index, &mut info) };
+            if res != ffi::CAPRESULT_OK {
+                continue;
+            }
+
+            let mut control = Control::from_info(&info);
+            if control.kind == Kind::Menu {
+                let values = (control.min..=control.max).take(MAX_MENU_ITEMS as usize);
+                for value in values {
+                    let mut name = [0 as std::os::raw::c_char; 32];
+                    let res = unsafe {
+                        ffi::Cap_getControlMenuItem(
+                            context,
+                            self.id,
+                            control.id,
+                            value,
+                            name.as_mut_ptr(),
+                            name.len() as u32,
+                        )
+                    };
+                    if res == ffi::CAPRESULT_OK {
+                        let name = unsafe { std::ffi::CStr::from_ptr(name.as_ptr()) };
+                        control.menu.push(MenuItem {
+                            value,
+                            name: name.to_string_lossy().into_owned(),
+                        });
+                    }
+                }
+            }
+            controls.push(control);
+        }
+
+        controls
+    }
+
+    /// Returns the control called `name`, see [`Control::is_named`]
+    pub fn find_control(&self, name: &str) -> io::Result<Control> {
+        self.controls()
+            .into_iter()
+            .find(|control| control.is_named(name))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("unknown control: {}", name),
+                )
+            })
+    }
+
+    /// Returns the current value of a control
+    pub fn control(&self, id: u32) -> io::Result<i32> {
+        let context = CONTEXT.lock().unwrap().inner;
+        let mut value = 0;
+        let res = unsafe { ffi::Cap_getControl(context, self.id, id, &mut value) };
+        property::result(res).map(|_| value)
+    }
+
+    /// Sets the value of a control
+    pub fn set_control(&self, id: u32, value: i32) -> io::Result<()> {
+        let context = CONTEXT.lock().unwrap().inner;
+        let res = unsafe { ffi::Cap_setControl(context, self.id, id, value) };
+        property::result(res)
+    }
+
+    /// Returns the current value of the control called `name`
+    pub fn control_by_name(&self, name: &str) -> io::Result<i32> {
+        let control = self.find_control(name)?;
+        self.control(control.id)
+    }
+
+    /// Sets the value of the control called `name`
+    pub fn set_control_by_name(&self, name: &str, value: i32) -> io::Result<()> {
+        let control = self.find_control(name)?;
+        self.set_control(control.id, value)
+    }
+
+    /// Returns a profile holding the current format and all supported properties
+    ///
+    /// Properties whose automatic mode is enabled are recorded as [`Value::Auto`]. Properties
+    /// that are supported but cannot be read are left out of the profile and returned along with
+    /// the error instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::{Device, Format, Stream};
+    ///
+    /// let dev = Device::new(0);
+    /// if let Some(dev) = &dev {
+    ///     if let Some(stream) = Stream::new(&dev, &Format::default()) {
+    ///         let (profile, unreadable) = stream.snapshot_profile();
+    ///         println!("Profile: {:?}", profile);
+    ///         for (prop, error) in unreadable {
+    ///             println!("Skipped {}: {}", prop, error);
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    pub fn snapshot_profile(&self) -> (CameraProfile, Vec<(Property, io::Error)>) {
+        let mut profile = CameraProfile::default().format(self.format);
+        let mut unreadable = Vec::new();
+        for prop in Property::all() {
+            if self.property_limits(*prop).is_err() {
+                continue;
+            }
+            let value = match self.auto_property(*prop) {
+                Ok(true) => Value::Auto,
+                _ => match self.property(*prop) {
+                    Ok(value) => Value::Manual(value),
+                    Err(error) => {
+                        unreadable.push((*prop, error));
+                        continue;
+                    }
+                },
+            };
+            profile.properties.insert(*prop, value);
+        }
+        (profile, unreadable)
+    }
+
+    /// Restores the properties of a profile
+    ///
+    /// All automatic modes are switched first, so manual values are not overridden by the
+    /// driver afterwards. Manual values are then set in [`profile::APPLY_ORDER`]. Failing
+    /// properties do not stop the others from being applied, they are returned instead. The
+    /// format of the profile is not applied, see [`CameraProfile::open`].
+    pub fn apply_profile(&self, profile: &CameraProfile) -> Vec<Failure> {
+        let mut failures = Vec::new();
+
+        for (prop, value) in &profile.properties {
+            let res = match value {
+                Value::Auto => self.set_auto_property(*prop, true),
+                // Properties without an automatic mode are always manual
+                Value::Manual(_) => match self.set_auto_property(*prop, false) {
+                    Err(e) if e.kind() == io::ErrorKind::Unsupported => Ok(()),
+                    res => res,
+                },
+            };
+            if let Err(error) = res {
+                failures.push(Failure {
+                    property: *prop,
+                    value: *value,
+                    error,
+                });
+            }
+        }
+
+        for prop in &profile::APPLY_ORDER {
+            let value = match profile.properties.get(prop) {
+                Some(Value::Manual(value)) => *value,
+                _ => continue,
+            };
+            if failures.iter().any(|f| f.property == *prop) {
+                continue;
+            }
+            if let Err(error) = self.set_property(*prop, value) {
+                failures.push(Failure {
+                    property: *prop,
+                    value: Value::Manual(value),
+                    error,
+                });
+            }
+        }
+
+        failures
+    }
 }
 
 impl Drop for Stream {
@@ -0,0 +1,344 @@
+use std::io;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::calibration::Remap;
+use crate::camera_set::DEFAULT_STALL_TIMEOUT;
+use crate::correction::Correction;
+use crate::device::Device;
+use crate::format::Format;
+use crate::frame::Frame;
+use crate::profile::{CameraProfile, Failure};
+use crate::property::{Property, Value};
+use crate::stream::Stream;
+use crate::transform::Orientation;
+
+/// Default time between reconnection attempts
+pub const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// State of a supervised stream
+pub enum State {
+    /// Frames are arriving
+    Running,
+    /// No frame arrived within the stall timeout, the stream is closed and being reopened
+    Stalled,
+}
+
+#[derive(Debug)]
+/// Event reported by a supervised stream
+pub enum Event {
+    /// The state changed
+    StateChanged {
+        /// Previous state
+        from: State,
+        /// New state
+        to: State,
+    },
+    /// An attempt to reopen the stream failed, it is retried after the retry interval
+    ReconnectFailed(io::Error),
+    /// A property could not be restored after reopening the stream
+    RestoreFailed(Failure),
+    /// A property could not be read when the stream was opened, so it is not restored on
+    /// reconnection
+    SnapshotFailed {
+        /// Property
+        property: Property,
+        /// Error reported by the driver
+        error: io::Error,
+    },
+}
+
+/// Per-stream processing that has to survive a reconnection
+#[derive(Debug, Clone)]
+struct Settings {
+    orientation: Orientation,
+    correction: Option<Correction>,
+    undistortion: Option<Remap>,
+}
+
+#[derive(Debug)]
+/// Stream that is reopened automatically when the camera stops delivering frames
+///
+/// Frame arrival is tracked through the stream frame counter. When it does not change for
+/// longer than the stall timeout, the stream is closed and the devices are enumerated again
+/// until the camera with the same unique ID shows up. It is then reopened in the same format,
+/// and the property settings, orientation, correction and undistortion are restored.
+///
+/// Frame arrival is only noticed while the stream is used or [`SupervisedStream::update`] is
+/// called, so call it regularly when the stream is idle.
+///
+/// # Example
+///
+/// ```
+/// use openpnp_capture::supervisor::{Event, SupervisedStream};
+/// use openpnp_capture::{Device, Format};
+/// use std::time::Duration;
+///
+/// let dev = Device::new(0);
+/// if let Some(dev) = &dev {
+///     if let Ok(mut stream) = SupervisedStream::new(&dev, &Format::default()) {
+///         match stream.next_frame(Duration::from_secs(5)) {
+///             Ok(frame) => println!("Frame {}", frame.sequence),
+///             Err(e) => println!("No frame: {}", e),
+///         }
+///         for event in stream.events() {
+///             if let Event::StateChanged { from, to } = event {
+///                 println!("{:?} -> {:?}", from, to);
+///             }
+///         }
+///     }
+/// }
+/// ```
+pub struct SupervisedStream {
+    unique_id: String,
+    /// Format and property settings restored on reconnection
+    profile: CameraProfile,
+    settings: Settings,
+    stream: Option<Stream>,
+    state: State,
+    /// Frame counter at the last update
+    frames: u32,
+    /// Host time at which the frame counter last changed
+    last_frame: Instant,
+    /// Host time of the last reconnection attempt
+    last_attempt: Option<Instant>,
+    stall_timeout: Duration,
+    retry_interval: Duration,
+    events: Vec<Event>,
+}
+
+impl SupervisedStream {
+    /// Opens a supervised stream
+    ///
+    /// The current property settings of the camera are recorded to be restored on
+    /// reconnection. Properties that cannot be read are reported as
+    /// [`Event::SnapshotFailed`].
+    pub fn new(dev: &Device, format: &Format) -> io::Result<Self> {
+        let stream = Stream::new(dev, format)
+            .ok_or_else(|| io::Error::other(format!("failed to open stream on {}", dev.name)))?;
+        let (profile, unreadable) = stream.snapshot_profile();
+        let events = unreadable
+            .into_iter()
+            .map(|(property, error)| Event::SnapshotFailed { property, error })
+            .collect();
+
+        Ok(SupervisedStream {
+            unique_id: dev.id.clone(),
+            profile,
+            settings: Settings {
+                orientation: Orientation::default(),
+                correction: None,
+                undistortion: None,
+            },
+            frames: stream.frame_count(),
+            stream: Some(stream),
+            state: State::Running,
+            last_frame: Instant::now(),
+            last_attempt: None,
+            stall_timeout: DEFAULT_STALL_TIMEOUT,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            events,
+        })
+    }
+
+    /// Builder: sets the time without new frames after which the stream is considered stalled
+    pub fn stall_timeout(mut self, timeout: Duration) -> Self {
+        self.stall_timeout = timeout;
+        self
+    }
+
+    /// Builder: sets the time between reconnection attempts
+    pub fn retry_interval(mut self, interval: Duration) -> Self {
+        self.retry_interval = interval;
+        self
+    }
+
+    /// Returns the unique ID of the supervised camera
+    pub fn unique_id(&self) -> &str {
+        &self.unique_id
+    }
+
+    /// Returns the current state
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns the underlying stream while it is open
+    pub fn stream(&self) -> Option<&Stream> {
+        self.stream.as_ref()
+    }
+
+    /// Returns the underlying stream for configuration while it is open
+    ///
+    /// Property changes made directly on the stream are not restored on reconnection unless
+    /// [`SupervisedStream::remember_settings`] is called afterwards.
+    pub fn stream_mut(&mut self) -> Option<&mut Stream> {
+        self.stream.as_mut()
+    }
+
+    /// Returns the events since the last call
+    pub fn events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Records the current property settings and processing of the stream for reconnection
+    pub fn remember_settings(&mut self) -> io::Result<()> {
+        let stream = self.open_stream()?;
+        let (profile, unreadable) = stream.snapshot_profile();
+        if let Some((prop, error)) = unreadable.into_iter().next() {
+            return Err(io::Error::new(
+                error.kind(),
+                format!("failed to read {}: {}", prop, error),
+            ));
+        }
+        let properties = profile.properties;
+        let settings = Settings {
+            orientation: stream.orientation(),
+            correction: stream.correction().cloned(),
+            undistortion: stream.undistortion().cloned(),
+        };
+        self.profile.properties = properties;
+        self.settings = settings;
+        Ok(())
+    }
+
+    /// Sets a property and records it for reconnection
+    pub fn set_property(&mut self, prop: Property, value: i32) -> io::Result<()> {
+        self.open_stream()?.set_property(prop, value)?;
+        self.profile.properties.insert(prop, Value::Manual(value));
+        Ok(())
+    }
+
+    /// Enables or disables the automatic mode of a property and records it for reconnection
+    pub fn set_auto_property(&mut self, prop: Property, enabled: bool) -> io::Result<()> {
+        let stream = self.open_stream()?;
+        stream.set_auto_property(prop, enabled)?;
+        let value = match enabled {
+            true => Value::Auto,
+            false => Value::Manual(stream.property(prop)?),
+        };
+        self.profile.properties.insert(prop, value);
+        Ok(())
+    }
+
+    fn open_stream(&self) -> io::Result<&Stream> {
+        self.stream
+            .as_ref()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotConnected, "stream is stalled"))
+    }
+
+    fn set_state(&mut self, state: State) {
+        if state != self.state {
+            self.events.push(Event::StateChanged {
+                from: self.state,
+                to: state,
+            });
+            self.state = state;
+        }
+    }
+
+    /// Checks for frame arrival and reconnects a stalled stream
+    ///
+    /// Returns the state after the check, events are queued for [`SupervisedStream::events`].
+    pub fn update(&mut self) -> State {
+        if let Some(stream) = &self.stream {
+            let frames = stream.frame_count();
+            if frames != self.frames {
+                self.frames = frames;
+                self.last_frame = Instant::now();
+            } else if self.last_frame.elapsed() > self.stall_timeout {
+                // Keep the processing that was set up on the stream since
+                self.settings = Settings {
+                    orientation: stream.orientation(),
+                    correction: stream.correction().cloned(),
+                    undistortion: stream.undistortion().cloned(),
+                };
+                self.stream = None;
+                self.last_attempt = None;
+                self.set_state(State::Stalled);
+            }
+        }
+
+        if self.stream.is_none() {
+            let due = match self.last_attempt {
+                Some(attempt) => attempt.elapsed() >= self.retry_interval,
+                None => true,
+            };
+            if due {
+                self.last_attempt = Some(Instant::now());
+                match self.reopen() {
+                    Ok(stream) => {
+                        self.frames = stream.frame_count();
+                        self.last_frame = Instant::now();
+                        self.stream = Some(stream);
+                        self.set_state(State::Running);
+                    }
+                    Err(e) => self.events.push(Event::ReconnectFailed(e)),
+                }
+            }
+        }
+
+        self.state
+    }
+
+    /// Finds the camera again and opens it with the recorded settings
+    fn reopen(&mut self) -> io::Result<Stream> {
+        Device::rescan()?;
+        let dev = Device::enumerate()
+            .into_iter()
+            .filter_map(Device::new)
+            .find(|dev| dev.id == self.unique_id)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("device not found: {}", self.unique_id),
+                )
+            })?;
+
+        let format = self.profile.format.unwrap_or_default();
+        let mut stream = Stream::new(&dev, &format)
+            .ok_or_else(|| io::Error::other(format!("failed to open stream on {}", dev.name)))?;
+        stream.set_orientation(self.settings.orientation);
+        stream.set_correction(self.settings.correction.clone())?;
+        stream.set_undistortion(self.settings.undistortion.clone())?;
+
+        for failure in stream.apply_profile(&self.profile) {
+            self.events.push(Event::RestoreFailed(failure));
+        }
+        Ok(stream)
+    }
+
+    /// Returns the next frame, reconnecting in between if the stream stalls
+    ///
+    /// Unlike polling [`Stream::poll`], this never blocks for longer than `timeout`.
+    pub fn next_frame(&mut self, timeout: Duration) -> io::Result<Frame> {
+        let deadline = Instant::now() + timeout;
+        let mut base = match self.update() {
+            State::Running => Some(self.frames),
+            State::Stalled => None,
+        };
+
+        loop {
+            let state = self.update();
+            if let (State::Running, Some(stream)) = (state, &self.stream) {
+                match base {
+                    Some(base) if self.frames != base => return stream.read_frame(),
+                    Some(_) => {}
+                    // Reconnected, wait for the first frame of the new stream
+                    None => base = Some(self.frames),
+                }
+            } else {
+                base = None;
+            }
+
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "no frame before deadline",
+                ));
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
@@ -0,0 +1,414 @@
+use crate::frame::Frame;
+use crate::roi::Roi;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// Digital scaling of a frame
+pub enum Scale {
+    /// Averages blocks of `n`x`n` pixels, partial blocks at the right and bottom are dropped
+    Bin(u32),
+    /// Resizes to the given size, picking the nearest pixel
+    Nearest {
+        /// Output width in pixels
+        width: u32,
+        /// Output height in pixels
+        height: u32,
+    },
+    /// Resizes to the given size, interpolating between the four nearest pixels
+    Bilinear {
+        /// Output width in pixels
+        width: u32,
+        /// Output height in pixels
+        height: u32,
+    },
+}
+
+impl Scale {
+    /// Returns the size of a scaled frame
+    pub fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        match *self {
+            Scale::Bin(n) => (width / n.max(1), height / n.max(1)),
+            Scale::Nearest { width, height } | Scale::Bilinear { width, height } => (width, height),
+        }
+    }
+
+    /// Returns a scaled copy of an RGB24 frame
+    ///
+    /// Sequence number and timestamp are kept.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::transform::Scale;
+    /// use openpnp_capture::Frame;
+    /// use std::time::Instant;
+    ///
+    /// let frame = Frame {
+    ///     width: 2,
+    ///     height: 2,
+    ///     sequence: 0,
+    ///     timestamp: Instant::now(),
+    ///     data: [0, 100, 100, 200].iter().flat_map(|v| vec![*v; 3]).collect(),
+    /// };
+    /// assert_eq!(Scale::Bin(2).apply(&frame).data, vec![100; 3]);
+    ///
+    /// let scaled = Scale::Nearest { width: 4, height: 4 }.apply(&frame);
+    /// assert_eq!(&scaled.data[..12], &[0, 0, 0, 0, 0, 0, 100, 100, 100, 100, 100, 100]);
+    ///
+    /// let scaled = Scale::Bilinear { width: 4, height: 1 }.apply(&frame);
+    /// assert_eq!(scaled.width, 4);
+    /// assert_eq!(scaled.data[3], 75);
+    /// ```
+    pub fn apply(&self, frame: &Frame) -> Frame {
+        let (width, height) = self.size(frame.width, frame.height);
+        let data = match *self {
+            Scale::Bin(n) => bin(frame, n.max(1), width, height),
+            Scale::Nearest { .. } => nearest(frame, width, height),
+            Scale::Bilinear { .. } => bilinear(frame, width, height),
+        };
+
+        Frame {
+            width,
+            height,
+            sequence: frame.sequence,
+            timestamp: frame.timestamp,
+            data,
+        }
+    }
+}
+
+fn bin(frame: &Frame, n: u32, width: u32, height: u32) -> Vec<u8> {
+    let stride = frame.width as usize * 3;
+    let n = n as usize;
+    let count = (n * n) as u32;
+    let mut data = Vec::with_capacity(width as usize * height as usize * 3);
+    let mut sums = vec![0u32; width as usize * 3];
+
+    for y in 0..height as usize {
+        sums.iter_mut().for_each(|sum| *sum = 0);
+        for row in frame.data[y * n * stride..].chunks(stride).take(n) {
+            for (i, sum) in sums.iter_mut().enumerate() {
+                let (x, c) = (i / 3, i % 3);
+                for dx in 0..n {
+                    *sum += row[(x * n + dx) * 3 + c] as u32;
+                }
+            }
+        }
+        data.extend(sums.iter().map(|sum| ((sum + count / 2) / count) as u8));
+    }
+    data
+}
+
+fn nearest(frame: &Frame, width: u32, height: u32) -> Vec<u8> {
+    let (sw, sh) = (frame.width as u64, frame.height as u64);
+    let mut data = Vec::with_capacity(width as usize * height as usize * 3);
+    if sw == 0 || sh == 0 {
+        data.resize(width as usize * height as usize * 3, 0);
+        return data;
+    }
+
+    // Map pixel centers
+    let xs: Vec<usize> = (0..width as u64)
+        .map(|x| ((2 * x + 1) * sw / (2 * width as u64)) as usize * 3)
+        .collect();
+    for y in 0..height as u64 {
+        let sy = ((2 * y + 1) * sh / (2 * height as u64)) as usize;
+        let row = &frame.data[sy * sw as usize * 3..(sy + 1) * sw as usize * 3];
+        for x in &xs {
+            data.extend_from_slice(&row[*x..*x + 3]);
+        }
+    }
+    data
+}
+
+fn bilinear(frame: &Frame, width: u32, height: u32) -> Vec<u8> {
+    let (sw, sh) = (frame.width as usize, frame.height as usize);
+    let mut data = Vec::with_capacity(width as usize * height as usize * 3);
+    if sw == 0 || sh == 0 {
+        data.resize(width as usize * height as usize * 3, 0);
+        return data;
+    }
+
+    // Source coordinate and weight of the right/bottom neighbour for every output coordinate
+    let map = |out: u32, src: usize| -> Vec<(usize, usize, f32)> {
+        (0..out)
+            .map(|i| {
+                let pos = ((i as f32 + 0.5) * src as f32 / out as f32 - 0.5).max(0.0);
+                let lo = (pos as usize).min(src - 1);
+                let hi = (lo + 1).min(src - 1);
+                (lo, hi, pos - lo as f32)
+            })
+            .collect()
+    };
+    let xs = map(width, sw);
+    let ys = map(height, sh);
+
+    let px = |x: usize, y: usize, c: usize| frame.data[(y * sw + x) * 3 + c] as f32;
+    for (y0, y1, wy) in &ys {
+        for (x0, x1, wx) in &xs {
+            for c in 0..3 {
+                let top = px(*x0, *y0, c) * (1.0 - wx) + px(*x1, *y0, c) * wx;
+                let bottom = px(*x0, *y1, c) * (1.0 - wx) + px(*x1, *y1, c) * wx;
+                data.push((top * (1.0 - wy) + bottom * wy).round() as u8);
+            }
+        }
+    }
+    data
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+/// Clockwise rotation
+pub enum Rotation {
+    /// No rotation
+    #[default]
+    R0,
+    /// Quarter turn clockwise
+    R90,
+    /// Half turn
+    R180,
+    /// Quarter turn counter-clockwise
+    R270,
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+/// Mounting orientation of a camera
+///
+/// The image is flipped first and rotated afterwards.
+pub struct Orientation {
+    /// Clockwise rotation
+    pub rotation: Rotation,
+    /// Mirror left and right
+    pub flip_horizontal: bool,
+    /// Mirror top and bottom
+    pub flip_vertical: bool,
+}
+
+/// Maps destination to source coordinates: `sx = x0 + ax * dx + bx * dy`, same for `sy`
+#[derive(Debug, Copy, Clone)]
+struct Mapping {
+    x0: i64,
+    ax: i64,
+    bx: i64,
+    y0: i64,
+    ay: i64,
+    by: i64,
+}
+
+impl Mapping {
+    fn source(&self, dx: i64, dy: i64) -> (i64, i64) {
+        (
+            self.x0 + self.ax * dx + self.bx * dy,
+            self.y0 + self.ay * dx + self.by * dy,
+        )
+    }
+}
+
+impl Orientation {
+    /// Returns the identity orientation
+    pub fn new() -> Self {
+        Orientation::default()
+    }
+
+    /// Builder: sets the clockwise rotation
+    pub fn rotate(mut self, rotation: Rotation) -> Self {
+        self.rotation = rotation;
+        self
+    }
+
+    /// Builder: mirrors left and right
+    pub fn flip_horizontal(mut self, flip: bool) -> Self {
+        self.flip_horizontal = flip;
+        self
+    }
+
+    /// Builder: mirrors top and bottom
+    pub fn flip_vertical(mut self, flip: bool) -> Self {
+        self.flip_vertical = flip;
+        self
+    }
+
+    /// Returns true if the orientation does not change the image
+    pub fn is_identity(&self) -> bool {
+        *self == Orientation::default()
+    }
+
+    /// Returns true if width and height are swapped
+    pub fn is_transposed(&self) -> bool {
+        matches!(self.rotation, Rotation::R90 | Rotation::R270)
+    }
+
+    /// Returns the size of an oriented image
+    pub fn size(&self, width: u32, height: u32) -> (u32, u32) {
+        if self.is_transposed() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Returns the mapping from oriented to source coordinates for a source image size
+    fn mapping(&self, width: u32, height: u32) -> Mapping {
+        let (w, h) = (width as i64 - 1, height as i64 - 1);
+
+        // Rotation: oriented coordinates to flipped image coordinates
+        let mut m = match self.rotation {
+            Rotation::R0 => Mapping {
+                x0: 0,
+                ax: 1,
+                bx: 0,
+                y0: 0,
+                ay: 0,
+                by: 1,
+            },
+            Rotation::R90 => Mapping {
+                x0: 0,
+                ax: 0,
+                bx: 1,
+                y0: h,
+                ay: -1,
+                by: 0,
+            },
+            Rotation::R180 => Mapping {
+                x0: w,
+                ax: -1,
+                bx: 0,
+                y0: h,
+                ay: 0,
+                by: -1,
+            },
+            Rotation::R270 => Mapping {
+                x0: w,
+                ax: 0,
+                bx: -1,
+                y0: 0,
+                ay: 1,
+                by: 0,
+            },
+        };
+
+        // Flips: flipped image coordinates to source coordinates
+        if self.flip_horizontal {
+            m.x0 = w - m.x0;
+            m.ax = -m.ax;
+            m.bx = -m.bx;
+        }
+        if self.flip_vertical {
+            m.y0 = h - m.y0;
+            m.ay = -m.ay;
+            m.by = -m.by;
+        }
+        m
+    }
+
+    /// Returns the source region that ends up in a region of the oriented image
+    ///
+    /// `width` and `height` are the size of the source image.
+    pub fn source_roi(&self, roi: &Roi, width: u32, height: u32) -> Roi {
+        if roi.is_empty() {
+            return Roi::new(0, 0, 0, 0);
+        }
+
+        let m = self.mapping(width, height);
+        let (ax, ay) = m.source(roi.x as i64, roi.y as i64);
+        let (bx, by) = m.source(
+            roi.x as i64 + roi.width as i64 - 1,
+            roi.y as i64 + roi.height as i64 - 1,
+        );
+        Roi::new(
+            ax.min(bx) as u32,
+            ay.min(by) as u32,
+            ((ax - bx).abs() + 1) as u32,
+            ((ay - by).abs() + 1) as u32,
+        )
+    }
+
+    /// Orients an image with `channels` bytes per pixel, e.g. 3 for RGB24 or 1 for grayscale
+    ///
+    /// `width` and `height` are the size of the source image, `dst` is resized to fit.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use openpnp_capture::transform::{Orientation, Rotation};
+    ///
+    /// // 3x2 grayscale image
+    /// let src = [1, 2, 3, 4, 5, 6];
+    /// let mut dst = Vec::new();
+    ///
+    /// Orientation::new().rotate(Rotation::R90).apply(&src, 3, 2, 1, &mut dst);
+    /// assert_eq!(dst, vec![4, 1, 5, 2, 6, 3]);
+    ///
+    /// Orientation::new().rotate(Rotation::R180).apply(&src, 3, 2, 1, &mut dst);
+    /// assert_eq!(dst, vec![6, 5, 4, 3, 2, 1]);
+    ///
+    /// Orientation::new().flip_horizontal(true).apply(&src, 3, 2, 1, &mut dst);
+    /// assert_eq!(dst, vec![3, 2, 1, 6, 5, 4]);
+    ///
+    /// Orientation::new()
+    ///     .flip_horizontal(true)
+    ///     .rotate(Rotation::R270)
+    ///     .apply(&src, 3, 2, 1, &mut dst);
+    /// assert_eq!(dst, vec![1, 4, 2, 5, 3, 6]);
+    /// ```
+    pub fn apply(&self, src: &[u8], width: u32, height: u32, channels: usize, dst: &mut Vec<u8>) {
+        let (dw, dh) = self.size(width, height);
+        let (dw, dh) = (dw as usize, dh as usize);
+        dst.resize(dw * dh * channels, 0);
+        if dw == 0 || dh == 0 {
+            return;
+        }
+
+        let m = self.mapping(width, height);
+        let stride = width as usize * channels;
+        let pixel = |x: i64, y: i64| y as usize * stride + x as usize * channels;
+
+        if !self.is_transposed() {
+            // Rows stay rows, copy or reverse them as a whole
+            for (dy, row) in dst.chunks_exact_mut(dw * channels).enumerate() {
+                let (_, sy) = m.source(0, dy as i64);
+                let start = pixel(0, sy);
+                let src_row = &src[start..start + stride];
+                if m.ax > 0 {
+                    row.copy_from_slice(src_row);
+                } else {
+                    for (d, s) in row
+                        .chunks_exact_mut(channels)
+                        .zip(src_row.chunks_exact(channels).rev())
+                    {
+                        d.copy_from_slice(s);
+                    }
+                }
+            }
+            return;
+        }
+
+        // Walk the destination in tiles so source reads stay within a few cache lines
+        const TILE: usize = 32;
+        for ty in (0..dh).step_by(TILE) {
+            for tx in (0..dw).step_by(TILE) {
+                for dy in ty..(ty + TILE).min(dh) {
+                    for dx in tx..(tx + TILE).min(dw) {
+                        let (sx, sy) = m.source(dx as i64, dy as i64);
+                        let s = pixel(sx, sy);
+                        let d = (dy * dw + dx) * channels;
+                        dst[d..d + channels].copy_from_slice(&src[s..s + channels]);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns an oriented copy of an RGB24 frame
+    pub fn apply_frame(&self, frame: &Frame) -> Frame {
+        let (width, height) = self.size(frame.width, frame.height);
+        let mut data = Vec::new();
+        self.apply(&frame.data, frame.width, frame.height, 3, &mut data);
+
+        Frame {
+            width,
+            height,
+            sequence: frame.sequence,
+            timestamp: frame.timestamp,
+            data,
+        }
+    }
+}
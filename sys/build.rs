@@ -50,8 +50,13 @@ fn main() {
     // Tell cargo to invalidate the built crate whenever the wrapper changes
     println!("cargo:rerun-if-changed=wrapper.h");
 
-    let bindings = bindgen::Builder::default()
-        .clang_arg("--include-directory=vendor/include")
+    let mut builder = bindgen::Builder::default().clang_arg("--include-directory=vendor/include");
+    if env::consts::OS == "linux" {
+        // The turbojpeg API is exposed for JPEG encoding and decoding
+        builder = builder.clang_arg("--include-directory=vendor/linux/contrib/libjpeg-turbo-dev");
+    }
+
+    let bindings = builder
         .header("wrapper.h")
         .parse_callbacks(Box::new(bindgen::CargoCallbacks))
         .generate()
@@ -1 +1,5 @@
 #include <openpnp-capture.h>
+
+#ifdef __linux__
+#include <turbojpeg.h>
+#endif